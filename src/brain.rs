@@ -1,106 +1,677 @@
 use std::{
-    collections::HashSet,
-    error::Error,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::{self, File},
-    io::{self, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+use chrono::{DateTime, Utc};
 use log::info;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::config::Data;
+use crate::{
+    config::Data,
+    error::{MdbError, PathContext},
+    format::Format,
+    frontmatter, links,
+    output::{self, Record},
+    store::{self, Backend},
+};
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct BrainData {
+    #[serde(default)]
     pub entries: HashSet<PathBuf>,
+    #[serde(default)]
+    pub meta: HashMap<PathBuf, Meta>,
+    /// Last value handed out per `counter` id generator, by template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub counters: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Meta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    /// Last time mdb opened the note in `$EDITOR`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opened: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+}
+
+impl Meta {
+    pub fn is_empty(&self) -> bool {
+        self.template.is_none() && self.created.is_none() && self.opened.is_none() && !self.archived
+    }
+
+    /// Fills in the fields of `other` this doesn't have yet
+    pub fn merge(&mut self, other: &Meta) {
+        if self.template.is_none() {
+            self.template = other.template.clone();
+        }
+        if self.created.is_none() {
+            self.created = other.created;
+        }
+        if self.opened.is_none() {
+            self.opened = other.opened;
+        }
+        self.archived |= other.archived;
+    }
 }
+
 impl BrainData {
-    pub(crate) fn add(&mut self, pwd: &PathBuf) {
-        self.entries.insert(pwd.to_owned());
+    pub fn upsert(&mut self, path: &Path, meta: Option<&Meta>) {
+        self.entries.insert(path.to_owned());
+        if let Some(meta) = meta {
+            self.meta.entry(path.to_owned()).or_default().merge(meta);
+        }
+    }
+
+    /// Moves the entry for `from` to `to`, keeping its metadata
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        self.entries.remove(from);
+        self.entries.insert(to.to_owned());
+        if let Some(meta) = self.meta.remove(from) {
+            self.meta.insert(to.to_owned(), meta);
+        }
+    }
+
+    /// Same brain with every path passed through `f`
+    fn map_paths(&self, f: impl Fn(&Path) -> PathBuf) -> BrainData {
+        BrainData {
+            entries: self.entries.iter().map(|path| f(path)).collect(),
+            meta: self
+                .meta
+                .iter()
+                .map(|(path, meta)| (f(path), meta.clone()))
+                .collect(),
+            counters: self.counters.clone(),
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.meta.remove(path);
+        self.entries.remove(path)
+    }
+
+    pub fn is_archived(&self, path: &Path) -> bool {
+        self.meta.get(path).is_some_and(|meta| meta.archived)
     }
 
-    fn list(&self) -> Vec<String> {
+    /// Most recent existing entry created from `template`, other than `exclude`
+    pub fn latest(&self, template: &str, exclude: &Path) -> Option<PathBuf> {
         self.entries
             .iter()
-            .map(|path| path.to_str().unwrap_or_default().to_string())
-            .collect()
+            .filter(|path| path.as_path() != exclude && path.exists())
+            .filter_map(|path| {
+                let meta = self.meta.get(path)?;
+                if meta.template.as_deref() != Some(template) {
+                    return None;
+                }
+                let created = match meta.created {
+                    Some(created) => created,
+                    None => DateTime::<Utc>::from(fs::metadata(path).ok()?.modified().ok()?),
+                };
+                Some((created, path))
+            })
+            .max()
+            .map(|(_, path)| path.to_owned())
+    }
+
+    /// Known notes sorted by path, hiding archived ones unless `archived` is set
+    pub fn list(&self, archived: bool) -> Vec<Note> {
+        let mut notes: Vec<Note> = self
+            .entries
+            .iter()
+            .filter(|path| archived || !self.is_archived(path))
+            .map(|path| Note::new(path.clone(), self.meta.get(path)))
+            .collect();
+        notes.sort_by(|a, b| a.path.cmp(&b.path));
+        notes
+    }
+}
+
+/// A known note with its metadata, as reported by `list`, `add` and `clean`
+#[derive(Serialize, Debug)]
+pub struct Note {
+    pub path: PathBuf,
+    pub template: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub opened: Option<DateTime<Utc>>,
+    pub archived: bool,
+}
+
+impl Note {
+    pub fn new(path: PathBuf, meta: Option<&Meta>) -> Note {
+        let meta = meta.cloned().unwrap_or_default();
+        Note {
+            path,
+            template: meta.template,
+            created: meta.created,
+            opened: meta.opened,
+            archived: meta.archived,
+        }
+    }
+}
+
+impl Record for Note {
+    const COLUMNS: &'static [&'static str] = &["path", "template", "created", "opened", "archived"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.path),
+            output::optional(&self.template),
+            output::date(&self.created),
+            output::date(&self.opened),
+            self.archived.to_string(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        output::path(&self.path)
+    }
+}
+
+/// A note moved by `mv` or `archive`
+#[derive(Serialize, Debug)]
+pub struct Moved {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub dry_run: bool,
+    /// Lines whose links to the note were rewritten
+    pub rewrites: Vec<Rewrite>,
+    /// Wiki links left alone, as they match other notes too
+    pub ambiguous: Vec<Ambiguous>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Rewrite {
+    pub note: PathBuf,
+    /// Where the note is written, `to` for the moved note itself
+    pub target: PathBuf,
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Ambiguous {
+    pub note: PathBuf,
+    pub line: usize,
+    pub target: String,
+}
+
+impl Moved {
+    /// Files the move changed: the note's old and new path, and the notes whose links were rewritten
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.from.clone(), self.to.clone()];
+        for rewrite in &self.rewrites {
+            if !paths.contains(&rewrite.target) {
+                paths.push(rewrite.target.clone());
+            }
+        }
+        paths
+    }
+}
+
+impl Record for Moved {
+    const COLUMNS: &'static [&'static str] = &["from", "to", "dry_run", "rewritten"];
+
+    fn row(&self) -> Vec<String> {
+        let rewritten: BTreeSet<String> = self
+            .rewrites
+            .iter()
+            .map(|rewrite| output::path(&rewrite.target))
+            .collect();
+        vec![
+            output::path(&self.from),
+            output::path(&self.to),
+            self.dry_run.to_string(),
+            rewritten.into_iter().collect::<Vec<String>>().join(","),
+        ]
+    }
+
+    /// The new path, or the rename and link changes as a diff for a dry run
+    fn plain(&self) -> String {
+        if !self.dry_run {
+            return output::path(&self.to);
+        }
+        let mut diff = vec![format!(
+            "rename {} -> {}",
+            self.from.display(),
+            self.to.display()
+        )];
+        let mut note = None;
+        for rewrite in &self.rewrites {
+            if note != Some(&rewrite.note) {
+                diff.push(format!(
+                    "--- {}\n+++ {}",
+                    rewrite.note.display(),
+                    rewrite.target.display()
+                ));
+                note = Some(&rewrite.note);
+            }
+            diff.push(format!(
+                "@@ line {} @@\n-{}\n+{}",
+                rewrite.line, rewrite.old, rewrite.new
+            ));
+        }
+        for ambiguous in &self.ambiguous {
+            diff.push(format!(
+                "ambiguous {}:{}: [[{}]] matches other notes too, left as is",
+                ambiguous.note.display(),
+                ambiguous.line,
+                ambiguous.target
+            ));
+        }
+        diff.join("\n")
+    }
+}
+
+/// A note `rm` forgot, and where its file went with `--delete`
+#[derive(Serialize, Debug)]
+pub struct Removed {
+    pub path: PathBuf,
+    pub trashed: Option<PathBuf>,
+}
+
+impl Record for Removed {
+    const COLUMNS: &'static [&'static str] = &["path", "trashed"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.path),
+            self.trashed
+                .as_deref()
+                .map(output::path)
+                .unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        output::path(&self.path)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Migrated {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub entries: usize,
+}
+
+impl Record for Migrated {
+    const COLUMNS: &'static [&'static str] = &["from", "to", "entries"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.from),
+            output::path(&self.to),
+            self.entries.to_string(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        format!(
+            "Migrated {} entries to {}, point `data` in your config at it",
+            self.entries,
+            self.to.display()
+        )
     }
 }
 
 pub struct Brain {}
 impl Brain {
-    pub(crate) fn add(data: &Data, pwd: PathBuf) -> Result<String, Box<dyn Error>> {
-        match pwd.try_exists()? {
-            true => {
-                info!("Brain::add {:?}", pwd);
-                let mut brain = Brain::load(data)?;
-                brain.add(&pwd);
-                Brain::save(data, &brain)?;
-                Ok(pwd.to_str().unwrap_or_default().to_string())
+    /// Resolves symlinks, `.` and `..` for existing files, lexically otherwise
+    pub fn canonical(path: &Path) -> PathBuf {
+        path.canonicalize()
+            .unwrap_or_else(|_| links::normalize(path))
+    }
+
+    /// Adds existing files, reporting the ones already known
+    pub fn add(data: &Data, paths: Vec<PathBuf>) -> Result<Vec<Note>, MdbError> {
+        let mut missing = vec![];
+        let mut canonical = BTreeSet::new();
+        for path in paths {
+            // Keep `mdb add name` working for `name.md`
+            let path = match path.extension().is_none() && !path.exists() {
+                true => path.with_extension("md"),
+                false => path,
+            };
+            if path.is_file() {
+                canonical.insert(Brain::canonical(&path));
+            } else {
+                missing.push(path);
             }
-            false => Err(Box::from(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("File {:?} not found", pwd),
-            ))),
         }
+        if !missing.is_empty() {
+            return Err(MdbError::Input(format!("File(s) {:?} not found", missing)));
+        }
+
+        info!("Brain::add {:?}", canonical);
+        let _lock = Brain::lock(data)?;
+        let brain = Brain::load(data)?;
+        let (known, new): (Vec<PathBuf>, Vec<PathBuf>) = canonical
+            .into_iter()
+            .partition(|path| brain.entries.contains(path));
+        for path in &known {
+            eprintln!("Already added {}", path.display());
+        }
+        let portable: Vec<PathBuf> = new
+            .iter()
+            .map(|path| data.config.roots.portable(path))
+            .collect();
+        data.config.store().upsert(&portable, None)?;
+        Ok(new.into_iter().map(|path| Note::new(path, None)).collect())
+    }
+
+    pub fn register(data: &Data, paths: &[PathBuf], template: &str) -> Result<(), MdbError> {
+        info!("Brain::register {:?} from {}", paths, template);
+        let meta = Meta {
+            template: Some(template.to_owned()),
+            created: Some(Utc::now()),
+            ..Default::default()
+        };
+        // Resolved like `add`, so a note reached through a symlink is known once
+        let portable: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| data.config.roots.portable(&Brain::canonical(path)))
+            .collect();
+        let _lock = Brain::lock(data)?;
+        data.config.store().upsert(&portable, Some(&meta))
+    }
+
+    pub fn latest(
+        data: &Data,
+        template: &str,
+        exclude: &Path,
+    ) -> Result<Option<PathBuf>, MdbError> {
+        let brain = Brain::load(data)?;
+        let latest = brain.latest(template, &Brain::canonical(exclude));
+        info!("Brain::latest for {}: {:?}", template, latest);
+        Ok(latest)
+    }
+
+    /// Next value of the sequential id `counter`, starting at 1
+    pub fn next_counter(data: &Data, counter: &str) -> Result<u64, MdbError> {
+        let _lock = Brain::lock(data)?;
+        let next = data.config.store().next_counter(counter)?;
+        info!("Brain::next_counter {}: {}", counter, next);
+        Ok(next)
     }
 
-    pub(crate) fn load(data: &Data) -> Result<BrainData, Box<dyn Error>> {
-        let data_file = PathBuf::from(shellexpand::tilde(&data.config.data).to_string());
-        info!("Brain::load {:?}", data_file);
-        fs::create_dir_all(data_file.parent().expect("Data must point to a file"))?;
-        let mut file = if data_file.exists() {
-            File::open(data_file).unwrap()
-        } else {
-            File::create(data_file).unwrap()
+    /// Records that `path` was just opened in `$EDITOR`
+    pub fn opened(data: &Data, path: &Path) -> Result<(), MdbError> {
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let Some(path) = [path.to_owned(), Brain::canonical(path)]
+            .into_iter()
+            .find(|path| brain.entries.contains(path))
+        else {
+            return Ok(());
         };
-        // Read config file
-        let mut contents = String::new();
-        let mut ret = BrainData::default();
-        if file.read_to_string(&mut contents).is_ok() {
-            ret = toml::from_str(&contents)?;
-            info!("Brain::load entries: {:?}", ret.entries);
+        info!("Brain::opened {:?}", path);
+        brain.meta.entry(path).or_default().opened = Some(Utc::now());
+        Brain::save(data, &brain)
+    }
+
+    /// Sets the `updated` frontmatter field of a note just edited, when its template tracks it
+    pub fn touch(data: &Data, path: &Path) -> Result<(), MdbError> {
+        let brain = Brain::load(data)?;
+        let template = [path.to_owned(), Brain::canonical(path)]
+            .iter()
+            .find_map(|path| brain.meta.get(path)?.template.clone())
+            .and_then(|template| data.get_template(&template));
+        if !template.is_some_and(|template| template.tracks_updated()) {
+            return Ok(());
+        }
+        let contents = fs::read_to_string(path).at(path)?;
+        if let Some(touched) = frontmatter::touch(&contents, Utc::now()) {
+            info!("Brain::touch {:?}", path);
+            fs::write(path, touched).at(path)?;
         }
-        file.flush()?;
+        Ok(())
+    }
 
-        Ok(ret)
+    /// Takes the advisory lock guarding a load-modify-save cycle, released when dropped
+    pub(crate) fn lock(data: &Data) -> Result<File, MdbError> {
+        let lock_file = store::sibling(&data.config.data_file(), "lock")?;
+        info!("Brain::lock {:?}", lock_file);
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_file)
+            .at(&lock_file)?;
+        lock.lock().at(&lock_file)?;
+        Ok(lock)
     }
 
-    pub(crate) fn save(data: &Data, brain: &BrainData) -> Result<(), Box<dyn Error>> {
-        let data_file = PathBuf::from(shellexpand::tilde(&data.config.data).to_string());
-        info!("Brain::save {:?}", data_file);
-        let _ = fs::create_dir_all(data_file.parent().expect("Data must point to a file"));
-        let mut file = File::create(&data_file)?;
+    pub fn load(data: &Data) -> Result<BrainData, MdbError> {
+        let roots = &data.config.roots;
+        let brain = data
+            .config
+            .store()
+            .load()?
+            .map_paths(|path| roots.resolve(path));
+        info!("Brain::load entries: {:?}", brain.entries);
+        Ok(brain)
+    }
+
+    pub(crate) fn save(data: &Data, brain: &BrainData) -> Result<(), MdbError> {
         info!("Brain::save entries: {:?}", brain.entries);
-        let toml = toml::to_string(brain)?;
-        file.write_all(toml.as_bytes())?;
-        file.flush()?;
-        Ok(())
+        let roots = &data.config.roots;
+        data.config
+            .store()
+            .save(&brain.map_paths(|path| roots.portable(path)))
+    }
+
+    /// Runs `f` on the brain while holding the lock, and saves what it changed
+    pub fn update<T>(data: &Data, f: impl FnOnce(&mut BrainData) -> T) -> Result<T, MdbError> {
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let result = f(&mut brain);
+        Brain::save(data, &brain)?;
+        Ok(result)
     }
 
-    pub(crate) fn list(data: &Data) -> Result<String, Box<dyn Error>> {
+    /// Copies the brain into a new data file using `backend`
+    pub fn migrate(
+        data: &Data,
+        backend: Backend,
+        to: Option<PathBuf>,
+    ) -> Result<Migrated, MdbError> {
+        let data_file = data.config.data_file();
+        let to = to.unwrap_or_else(|| data_file.with_extension(backend.extension()));
+        info!("Brain::migrate {:?} -> {:?}", data_file, to);
+        if to.exists() {
+            return Err(MdbError::Input(format!("File {:?} already exists", to)));
+        }
+        let _lock = Brain::lock(data)?;
+        let brain = data.config.store().load()?;
+        backend.store(to.clone()).save(&brain)?;
+        Ok(Migrated {
+            from: data_file,
+            to,
+            entries: brain.entries.len(),
+        })
+    }
+
+    pub fn list(data: &Data, archived: bool) -> Result<Vec<Note>, MdbError> {
         info!("Brain::list");
         let brain = Brain::load(data)?;
-        let list = brain.list().join("\n");
-        println!("{}", list);
-        Ok(list)
+        Ok(brain.list(archived))
+    }
+
+    /// Moves the note `from` to `to` and rewrites links to it in every known note
+    pub fn mv(data: &Data, from: PathBuf, to: PathBuf, dry_run: bool) -> Result<Moved, MdbError> {
+        info!("Brain::mv {:?} -> {:?}", from, to);
+        let from = Brain::canonical(&from);
+        let mut to = links::normalize(&to);
+        if to.is_dir() {
+            to.push(from.file_name().unwrap_or_default());
+        }
+        if to.extension().is_none() {
+            to.set_extension(from.extension().unwrap_or_default());
+        }
+        if !from.is_file() {
+            return Err(MdbError::Input(format!("File {:?} not found", from)));
+        }
+        if to.exists() {
+            return Err(MdbError::Input(format!("File {:?} already exists", to)));
+        }
+
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let mut notes: Vec<PathBuf> = brain.entries.iter().cloned().collect();
+        notes.sort();
+        if !brain.entries.contains(&from) {
+            notes.push(from.clone());
+        }
+        let from_dir = from.parent().unwrap_or(Path::new("/"));
+        let to_dir = to.parent().unwrap_or(Path::new("/"));
+        let mut changes = vec![];
+        let mut rewrites = vec![];
+        let mut ambiguous = vec![];
+        for note in notes.iter().filter(|note| note.is_file()) {
+            let contents = fs::read_to_string(note).at(note)?;
+            let (target, before, after) = match note == &from {
+                true => (to.clone(), from_dir, to_dir),
+                false => (
+                    note.clone(),
+                    note.parent().unwrap_or(from_dir),
+                    note.parent().unwrap_or(from_dir),
+                ),
+            };
+            let format = Format::from_path(note);
+            let (rewritten, skipped) =
+                links::rewrite(&contents, format, before, after, &from, &to, &notes);
+            ambiguous.extend(skipped.into_iter().map(|link| Ambiguous {
+                note: note.clone(),
+                line: link.line,
+                target: link.target,
+            }));
+            if rewritten == contents {
+                continue;
+            }
+            for (index, (old, new)) in contents.lines().zip(rewritten.lines()).enumerate() {
+                if old != new {
+                    rewrites.push(Rewrite {
+                        note: note.clone(),
+                        target: target.clone(),
+                        line: index + 1,
+                        old: old.to_string(),
+                        new: new.to_string(),
+                    });
+                }
+            }
+            changes.push((target, rewritten));
+        }
+        let moved = Moved {
+            from,
+            to,
+            dry_run,
+            rewrites,
+            ambiguous,
+        };
+        if dry_run {
+            return Ok(moved);
+        }
+        for ambiguous in &moved.ambiguous {
+            eprintln!(
+                "Left [[{}]] in {}:{} alone, it matches other notes too",
+                ambiguous.target,
+                ambiguous.note.display(),
+                ambiguous.line
+            );
+        }
+        let (from, to) = (&moved.from, &moved.to);
+
+        if fs::rename(from, to).is_err() {
+            fs::copy(from, to).at(to)?;
+            fs::remove_file(from).at(from)?;
+        }
+        for (target, rewritten) in &changes {
+            fs::write(target, rewritten).at(target)?;
+        }
+        brain.rename(from, to);
+        Brain::save(data, &brain)?;
+        Ok(moved)
+    }
+
+    /// Forgets `path`, and moves the file into the trash dir when `delete` is set
+    pub fn rm(data: &Data, path: PathBuf, delete: bool) -> Result<Removed, MdbError> {
+        let path = Brain::canonical(&path);
+        info!("Brain::rm {:?}", path);
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let known = brain.remove(&path);
+        let trash = delete && path.is_file();
+        if !(known || trash) {
+            return Err(MdbError::Brain(format!("{:?} is not a known note", path)));
+        }
+        let mut trashed = None;
+        if trash {
+            let trash = data.config.trash_dir();
+            fs::create_dir_all(&trash).at(&trash)?;
+            let mut target = trash.join(path.file_name().unwrap_or_default());
+            if target.exists() {
+                target = trash.join(format!(
+                    "{}-{}",
+                    Utc::now().format("%Y%m%d%H%M%S"),
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ));
+            }
+            info!("Brain::rm trashing to {:?}", target);
+            if fs::rename(&path, &target).is_err() {
+                fs::copy(&path, &target).at(&target)?;
+                fs::remove_file(&path).at(&path)?;
+            }
+            trashed = Some(target);
+        }
+        Brain::save(data, &brain)?;
+        Ok(Removed { path, trashed })
+    }
+
+    /// Moves `path` into the archive root and hides it from `list`
+    pub fn archive(data: &Data, path: PathBuf) -> Result<Moved, MdbError> {
+        let path = Brain::canonical(&path);
+        info!("Brain::archive {:?}", path);
+        let archive = data
+            .config
+            .archive_dir()
+            .unwrap_or_else(|| path.parent().unwrap_or(Path::new("/")).join("archive"));
+        fs::create_dir_all(&archive).at(&archive)?;
+        let moved = Brain::mv(data, path, archive, false)?;
+        Brain::update(data, |brain| {
+            brain.meta.entry(moved.to.clone()).or_default().archived = true;
+        })?;
+        Ok(moved)
     }
 
-    pub(crate) fn clean(data: &Data) -> Result<String, Box<dyn Error>> {
+    pub fn clean(data: &Data) -> Result<Vec<Note>, MdbError> {
         info!("Brain::clean");
-        let brain = Brain::load(data)?;
-        let removals = &brain
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let mut removals = brain
             .entries
             .iter()
-            .filter(|fil| !fil.exists())
-            .collect::<Vec<&PathBuf>>();
-        let mut brain = Brain::load(data)?;
+            .filter(|fil| data.config.roots.mounted(fil) && !fil.exists())
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        removals.sort();
         info!("Brain::clean removals: {:?}", removals);
-        for removal in removals {
-            brain.entries.remove(removal.to_owned());
-        }
+        let removed = removals
+            .into_iter()
+            .map(|removal| {
+                let note = Note::new(removal.clone(), brain.meta.get(&removal));
+                brain.remove(&removal);
+                note
+            })
+            .collect();
         Brain::save(data, &brain)?;
-        Ok(removals.len().to_string())
+        Ok(removed)
     }
 }
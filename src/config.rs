@@ -1,12 +1,37 @@
 pub static APPLICATION_NAME: &str = "mdb";
+/// Shorter values are left alone by `templates save --placeholders`
+const MIN_PLACEHOLDER_LEN: usize = 3;
+/// Ids tried by `TemplateName::Id` before giving up
+const MAX_ID_ATTEMPTS: u32 = 100;
+const BLOCK_MARKER: &str = "$BLOCK(";
+/// Block a child template's own body fills in its parent
+const CONTENT_BLOCK: &str = "content";
 use crate::brain::Brain;
+use crate::error::{MdbError, PathContext};
+use crate::export::Export;
+use crate::format::{self, Format};
+use crate::frontmatter::{self, Frontmatter};
+use crate::git::{Git, GitConfig};
+use crate::hooks::{Hook, Hooks};
+use crate::ids::{self, IdKind};
+use crate::import::{Conflict, Import};
+use crate::links;
+use crate::lsp::Lsp;
+use crate::output::{self, Output, OutputFormat, Record, Written};
+use crate::roots::Roots;
+use crate::search::Search;
+use crate::serve::Server;
+use crate::store::{Backend, BrainStore};
+use crate::tasks::{self, TaskFilter, Tasks};
+use crate::watch::Watch;
 use chrono::Utc;
 use log::info;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env::{self, var};
-use std::error::Error;
+use std::fs;
 use std::io::{self, prelude::*};
-use std::path::Path;
+use std::path::{Component, Path};
 use std::process::Command;
 use std::{fs::File, path::PathBuf};
 
@@ -16,6 +41,33 @@ pub struct Data {
     pub templates: Vec<Template>,
 }
 impl Data {
+    /// Reads `config.toml` in the config dir, creating the dir and an empty file if needed
+    pub fn load() -> Result<Data, MdbError> {
+        if dirs::config_dir().is_none() {
+            return Err(MdbError::Config(
+                "No config dir found, is $HOME set?".into(),
+            ));
+        }
+        let config_dir = Template::config_dir();
+        let config_file_path = config_dir.join("config.toml");
+        info!("{:?}", config_file_path);
+        fs::create_dir_all(&config_dir).at(&config_dir)?;
+        if !config_file_path.exists() {
+            //TODO: fill with default config
+            File::create(&config_file_path).at(&config_file_path)?;
+        }
+        Data::from_file(&config_file_path)
+    }
+
+    /// Parses a config file and checks its templates
+    pub fn from_file(path: &Path) -> Result<Data, MdbError> {
+        let contents = fs::read_to_string(path).at(path)?;
+        let data: Data = toml::from_str(&contents)
+            .map_err(|err| MdbError::Config(format!("{}: {}", path.display(), err)))?;
+        data.check_templates()?;
+        Ok(data)
+    }
+
     pub fn get_default_template(&self) -> Option<&Template> {
         self.templates.iter().find(|&x| x.id == "default")
     }
@@ -26,24 +78,120 @@ impl Data {
     pub fn template_file_exists(tmpl: &str) -> bool {
         Template::get_path(tmpl).exists()
     }
+
+    /// Fails on templates extending an unknown parent or, through their parents, themselves
+    pub fn check_templates(&self) -> Result<(), MdbError> {
+        for template in &self.templates {
+            let mut chain = vec![template.id.as_str()];
+            let mut current = template;
+            while let Some(parent) = &current.extends {
+                current = self.get_template(parent).ok_or_else(|| {
+                    MdbError::Config(format!(
+                        "Template `{}` extends unknown `{}`",
+                        current.id, parent
+                    ))
+                })?;
+                let cycle = chain.contains(&current.id.as_str());
+                chain.push(&current.id);
+                if cycle {
+                    return Err(MdbError::Config(format!(
+                        "Templates extend each other: {}",
+                        chain.join(" -> ")
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub data: String,
+    pub backend: Option<Backend>,
+    #[serde(default)]
+    pub roots: Roots,
+    pub trash: Option<String>,
+    pub archive: Option<String>,
+    #[serde(default)]
+    pub git: GitConfig,
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+impl Config {
+    pub fn data_file(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.data).to_string())
+    }
+
+    /// The store for `data`, picked by `backend` or else the file extension
+    pub fn store(&self) -> Box<dyn BrainStore> {
+        let file = self.data_file();
+        self.backend
+            .unwrap_or_else(|| Backend::from_path(&file))
+            .store(file)
+    }
+
+    /// Where `rm --delete` moves notes, defaults to `trash` next to the data file
+    pub fn trash_dir(&self) -> PathBuf {
+        match &self.trash {
+            Some(trash) => PathBuf::from(shellexpand::tilde(trash).to_string()),
+            None => self
+                .data_file()
+                .parent()
+                .unwrap_or(Path::new("/"))
+                .join("trash"),
+        }
+    }
+
+    pub fn archive_dir(&self) -> Option<PathBuf> {
+        self.archive
+            .as_ref()
+            .map(|archive| PathBuf::from(shellexpand::tilde(archive).to_string()))
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ExecCommand {
     run: String,
+    #[serde(default)]
     args: Vec<String>,
 }
 
+impl ExecCommand {
+    /// Runs the command, returning what it printed
+    fn output(&self) -> Result<String, MdbError> {
+        self.run_with(&[]).map_err(MdbError::Exec)
+    }
+
+    /// Runs the command with extra environment variables, returning what it printed
+    /// or why it failed
+    pub(crate) fn run_with(&self, env: &[(String, String)]) -> Result<String, String> {
+        info!("{:?}", self);
+        let output = Command::new(&self.run)
+            .args(&self.args)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .output()
+            .map_err(|err| format!("Running `{}`: {}", self.run, err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let mut msg = format!("`{}` {}", self.run, output.status);
+            if !stderr.trim().is_empty() {
+                msg = format!("{}: {}", msg, stderr.trim());
+            }
+            return Err(msg);
+        }
+        let out = String::from_utf8_lossy(&output.stdout);
+        info!("{:?}", &out);
+        Ok(out.to_string())
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum TemplateName {
     Text(String),
     Exec(ExecCommand),
+    Id(IdKind),
 }
 
 #[derive(Deserialize, Debug)]
@@ -52,76 +200,245 @@ pub struct Template {
     dir: Option<String>,
     content: Option<String>,
     name: Option<TemplateName>,
+    carryover: Option<bool>,
+    migrate: Option<bool>,
+    extension: Option<String>,
+    scaffold: Option<String>,
+    #[serde(default)]
+    existing: Existing,
+    /// Parent template whose `$BLOCK(name)` slots this one fills
+    extends: Option<String>,
+    #[serde(default)]
+    blocks: BTreeMap<String, String>,
+    frontmatter: Option<Frontmatter>,
+    #[serde(default)]
+    hooks: Hooks,
+}
+
+/// What a scaffold does with files that are already in the target
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Existing {
+    #[default]
+    Skip,
+    /// Append the rendered file unless the existing one already contains it
+    Merge,
+    Overwrite,
 }
 pub type OptStr = Option<String>;
+/// The note tasks were carried over from, and its contents
+type Previous = (PathBuf, String);
+/// How the `mdb` binary names the note to open, not part of the library's API
+#[doc(hidden)]
 #[derive(Debug, PartialEq)]
 pub enum Named {
     Default,
-    Name(OptStr),
-    Template(OptStr),
-    TemplateWithName(OptStr, OptStr),
+    Name(String),
+    Template(String),
+    TemplateWithName(String, String),
 }
 
+/// A command of the `mdb` binary, not part of the library's API
+#[doc(hidden)]
 #[derive(Debug)]
 pub enum Action {
     Default(Named),
     New(Named),
-    Add(Named),
+    Add(Vec<PathBuf>),
     // Scan(PathBuf),
-    List,
+    List(bool),
     Clean,
+    Remove(PathBuf, bool),
+    Archive(PathBuf),
+    Move(PathBuf, PathBuf, bool),
+    Migrate(Backend, Option<PathBuf>),
+    Tasks(TaskFilter),
+    TaskDone(String),
+    Templates,
+    SaveTemplate(PathBuf, String, bool),
+    ExportHtml(PathBuf, Vec<String>),
+    ImportObsidian(PathBuf, bool),
+    ExportJson(Option<PathBuf>, bool, bool),
+    ImportJson(PathBuf, Conflict),
+    Search(String, bool),
+    Serve(u16),
+    Lsp,
+    Watch(OutputFormat),
+    History(PathBuf),
+    Restore(PathBuf, String),
+}
+
+/// A note created or opened from a template
+#[derive(Serialize, Debug)]
+pub struct Created {
+    pub path: PathBuf,
+    pub template: String,
+}
+
+impl Record for Created {
+    const COLUMNS: &'static [&'static str] = &["path", "template"];
+
+    fn row(&self) -> Vec<String> {
+        vec![output::path(&self.path), self.template.clone()]
+    }
+
+    fn plain(&self) -> String {
+        output::path(&self.path)
+    }
+}
+
+/// A template listed by `templates`
+#[derive(Serialize, Debug)]
+pub struct TemplateEntry {
+    pub id: String,
+    /// Template file in the config dir, if it has one
+    pub file: Option<PathBuf>,
+    /// Whether `[[templates]]` in the config has it
+    pub configured: bool,
+}
+
+impl Record for TemplateEntry {
+    const COLUMNS: &'static [&'static str] = &["id", "file", "configured"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.file.as_deref().map(output::path).unwrap_or_default(),
+            self.configured.to_string(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        self.id.clone()
+    }
 }
 
 impl Named {
     pub fn from_template_and_name(template: OptStr, name: OptStr) -> Named {
-        if name.is_some() && template.is_some() {
-            Named::TemplateWithName(template, name)
-        } else if name.is_some() {
-            Named::Name(name)
-        } else if template.is_some() {
-            Named::Template(template)
-        } else {
-            Named::Default
+        match (template, name) {
+            (Some(template), Some(name)) => Named::TemplateWithName(template, name),
+            (None, Some(name)) => Named::Name(name),
+            (Some(template), None) => Named::Template(template),
+            (None, None) => Named::Default,
         }
     }
 }
 
 impl Template {
-    fn err(msg: String) -> io::Result<String> {
-        Err(io::Error::new(io::ErrorKind::NotFound, msg))
+    fn err<T>(msg: String) -> Result<T, MdbError> {
+        Err(MdbError::Template(msg))
     }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
     pub fn config_dir() -> PathBuf {
-        let mut path = dirs::config_dir().expect("Must have a config dir set");
+        // `Data::load` refuses to run without one
+        let mut path = dirs::config_dir().unwrap_or_default();
         path.push(APPLICATION_NAME);
         path
     }
 
+    /// `<tmpl>.<ext>` in the config dir for the first note extension that exists, else `.md`
     pub fn get_path(tmpl: &str) -> PathBuf {
-        let mut path = tmpl.to_owned();
-        path.push_str(".md");
-        Template::config_dir().join(path)
+        let dir = Template::config_dir();
+        format::EXTENSIONS
+            .iter()
+            .map(|(ext, _)| dir.join(format!("{}.{}", tmpl, ext)))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| dir.join(format!("{}.md", tmpl)))
     }
 
-    pub fn create(&self, path: PathBuf, name: &String, overwrite: bool) -> io::Result<String> {
-        let mut contents = match &self.content {
-            Some(template) => template.clone(),
-            None => {
-                let template = Template::get_path(&self.id);
-                if !template.exists() {
-                    return Template::err(format!("Template {} not found", self.id));
-                }
-                let mut template_file = File::open(template).unwrap();
-                let mut contents = String::new();
-                template_file.read_to_string(&mut contents)?;
-                contents
+    /// Extension of created notes, taken from the template file, or the parent's,
+    /// unless set explicitly
+    fn extension(&self, data: &Data) -> String {
+        if let Some(extension) = &self.extension {
+            return extension.trim_start_matches('.').to_string();
+        }
+        let template = Template::get_path(&self.id);
+        let parent = self.extends.as_ref().and_then(|id| data.get_template(id));
+        match (&self.content, template.extension(), parent) {
+            (None, Some(extension), _) if template.exists() => extension.to_string_lossy().into(),
+            (_, _, Some(parent)) => parent.extension(data),
+            _ => "md".into(),
+        }
+    }
+
+    pub fn tracks_updated(&self) -> bool {
+        self.frontmatter
+            .as_ref()
+            .is_some_and(Frontmatter::tracks_updated)
+    }
+
+    /// The template's own text, from `content` or its file in the config dir
+    fn body(&self) -> Result<Option<String>, MdbError> {
+        if let Some(content) = &self.content {
+            return Ok(Some(content.clone()));
+        }
+        let template = Template::get_path(&self.id);
+        match template.exists() {
+            true => Ok(Some(fs::read_to_string(&template).at(&template)?)),
+            false => Ok(None),
+        }
+    }
+
+    /// Renders the template into its parents: its own body fills the parent's `$BLOCK(content)`,
+    /// and its `blocks` fill the named slots, overriding the parents' defaults
+    fn layout(
+        &self,
+        data: &Data,
+        mut blocks: BTreeMap<String, String>,
+    ) -> Result<String, MdbError> {
+        for (name, block) in &self.blocks {
+            blocks.entry(name.clone()).or_insert_with(|| block.clone());
+        }
+        let body = match (self.body()?, &self.extends) {
+            (Some(body), _) => body,
+            (None, Some(_)) => String::new(),
+            (None, None) => {
+                return Template::err(format!(
+                    "`{}` has no `content` and no file {}",
+                    self.id,
+                    Template::get_path(&self.id).display()
+                ))
             }
         };
+        let body = Template::fill_blocks(&body, &blocks);
+        let Some(parent) = &self.extends else {
+            return Ok(body);
+        };
+        let Some(parent) = data.get_template(parent) else {
+            return Template::err(format!("`{}` extends unknown `{}`", self.id, parent));
+        };
+        blocks.insert(CONTENT_BLOCK.into(), body);
+        parent.layout(data, blocks)
+    }
 
-        // Load the template and inject the environment variables
-        Template::inject_variables(&mut contents, name, &path);
+    /// Replaces each `$BLOCK(name)` with its block, or nothing when no template fills it
+    fn fill_blocks(body: &str, blocks: &BTreeMap<String, String>) -> String {
+        let mut filled = String::new();
+        let mut rest = body;
+        while let Some(start) = rest.find(BLOCK_MARKER) {
+            let after = &rest[start + BLOCK_MARKER.len()..];
+            let Some(end) = after.find(')') else {
+                break;
+            };
+            filled.push_str(&rest[..start]);
+            filled.push_str(blocks.get(&after[..end]).map_or("", String::as_str));
+            rest = &after[end + 1..];
+        }
+        filled.push_str(rest);
+        filled
+    }
 
-        // Create the target new file and insert the template text
-        let mut file_path = match &self.dir {
+    /// `dir` when the template sets one, else `pwd`
+    fn target_dir(&self, pwd: &Path) -> Result<PathBuf, MdbError> {
+        let target = match &self.dir {
             Some(dir) => {
                 let path = PathBuf::from(shellexpand::tilde(dir).to_string());
                 if !path.exists() {
@@ -129,35 +446,244 @@ impl Template {
                 }
                 path
             }
-            None => path.clone(),
+            None => pwd.to_owned(),
         };
-        if !file_path.exists() {
+        if !target.exists() {
             return Template::err(format!(
                 "Target dir {} not found",
-                file_path.to_str().unwrap_or_default()
+                target.to_str().unwrap_or_default()
             ));
         }
+        Ok(target)
+    }
+
+    /// Path of the note `name` in the template's dir, or `pwd`
+    pub fn note_path(&self, data: &Data, pwd: &Path, name: &str) -> Result<PathBuf, MdbError> {
+        let mut file_path = self.target_dir(pwd)?;
         file_path.push(name);
-        file_path.set_extension("md");
+        file_path.set_extension(self.extension(data));
+        Ok(file_path)
+    }
+
+    /// Contents of a new note `name` in `pwd`, without writing anything
+    pub fn render(&self, data: &Data, pwd: &Path, name: &str) -> Result<String, MdbError> {
+        if self.scaffold.is_some() {
+            return Template::err(format!("Template {} renders a scaffold", self.id));
+        }
+        let file_path = self.note_path(data, pwd, name)?;
+        Ok(self.render_note(data, pwd, name, &file_path)?.0)
+    }
+
+    /// Rendered contents, and the previous note tasks were carried over from with its contents
+    fn render_note(
+        &self,
+        data: &Data,
+        pwd: &Path,
+        name: &str,
+        file_path: &Path,
+    ) -> Result<(String, Option<Previous>), MdbError> {
+        let mut contents = self.layout(data, BTreeMap::new())?;
+
+        // Load the template and inject the environment variables
+        Template::inject_variables(&mut contents, name, pwd);
+
+        // Roll unchecked tasks over from the previous note of this template
+        let previous = match self.carryover.unwrap_or_default() {
+            true => Brain::latest(data, &self.id, file_path)?,
+            false => None,
+        };
+        let previous = match previous {
+            Some(previous) => {
+                let previous_contents = fs::read_to_string(&previous).at(&previous)?;
+                Some((previous, previous_contents))
+            }
+            None => None,
+        };
+        let carried = match &previous {
+            Some((previous, contents)) => tasks::carryover(contents, Format::from_path(previous)),
+            None => String::new(),
+        };
+        contents = contents.replace("$CARRYOVER", &carried);
+
+        let frontmatter = self
+            .frontmatter
+            .as_ref()
+            .filter(|_| Format::from_path(file_path).takes_frontmatter());
+        if let Some(frontmatter) = frontmatter {
+            let header = frontmatter.render(&frontmatter::Values {
+                id: name,
+                template: &self.id,
+                created: Utc::now(),
+            });
+            contents = header + &contents;
+        }
+        Ok((contents, previous))
+    }
+
+    /// Writes the note `name`, or the scaffold, and adds it to the brain.
+    /// An existing note is only added, unless `overwrite` is set.
+    pub fn create(
+        &self,
+        data: &Data,
+        path: PathBuf,
+        name: &str,
+        overwrite: bool,
+    ) -> Result<PathBuf, MdbError> {
+        // Resolve the target new file
+        if let Some(scaffold) = &self.scaffold {
+            let target = self.target_dir(&path)?;
+            let opened = self.scaffold(data, scaffold, &target, name)?;
+            Hook::OnCreate.run(data, Some(self), &opened, name, &target);
+            return Ok(opened);
+        }
+        let file_path = self.note_path(data, &path, name)?;
         if file_path.exists() && !overwrite {
-            return Ok(file_path.to_str().unwrap_or_default().into());
+            Brain::register(data, std::slice::from_ref(&file_path), &self.id)?;
+            return Ok(file_path);
+        }
+
+        let (contents, previous) = self.render_note(data, &path, name, &file_path)?;
+
+        // Create the target new file and insert the template text
+        let mut new_file = File::create(&file_path).at(&file_path)?;
+        new_file.write_all(contents.as_bytes()).at(&file_path)?;
+        Brain::register(data, std::slice::from_ref(&file_path), &self.id)?;
+
+        if let Some((previous, previous_contents)) = previous {
+            if self.migrate.unwrap_or_default() {
+                info!("Migrating tasks in {:?}", previous);
+                let migrated = tasks::migrate(&previous_contents, Format::from_path(&previous));
+                fs::write(&previous, migrated).at(&previous)?;
+            }
+        }
+        Hook::OnCreate.run(data, Some(self), &file_path, name, &path);
+        Ok(file_path)
+    }
+
+    /// Copies the tree `scaffolds/<scaffold>` from the config dir into `target`,
+    /// rendering variables in file names and contents
+    fn scaffold(
+        &self,
+        data: &Data,
+        scaffold: &str,
+        target: &Path,
+        name: &str,
+    ) -> Result<PathBuf, MdbError> {
+        let source = Template::config_dir().join("scaffolds").join(scaffold);
+        if !source.is_dir() {
+            return Template::err(format!(
+                "Scaffold {} not found",
+                source.to_str().unwrap_or_default()
+            ));
         }
-        let mut new_file = File::create(&file_path)?;
-        new_file.write_all(contents.as_bytes())?;
-        Ok(file_path.to_str().unwrap_or_default().into())
+        let mut files = vec![];
+        Template::walk(&source, &mut files)?;
+        files.sort();
+
+        let mut notes = vec![];
+        for file in files {
+            let mut relative = file
+                .strip_prefix(&source)
+                .unwrap_or(&file)
+                .to_str()
+                .unwrap_or_default()
+                .to_string();
+            Template::inject_variables(&mut relative, name, target);
+            let created = target.join(Template::scaffold_path(&relative)?);
+            let exists = created.exists();
+            if exists && self.existing == Existing::Skip {
+                info!("Scaffold skipping existing {:?}", created);
+                continue;
+            }
+            let dir = created.parent().unwrap_or(target);
+            fs::create_dir_all(dir).at(dir)?;
+            match fs::read_to_string(&file) {
+                Ok(mut contents) => {
+                    Template::inject_variables(&mut contents, name, target);
+                    if exists && self.existing == Existing::Merge {
+                        let current = fs::read_to_string(&created).at(&created)?;
+                        if !current.contains(&contents) {
+                            fs::write(&created, format!("{}\n{}", current, contents))
+                                .at(&created)?;
+                        }
+                    } else {
+                        fs::write(&created, contents).at(&created)?;
+                    }
+                }
+                // Not text, copy as is
+                Err(_) if !exists || self.existing == Existing::Overwrite => {
+                    fs::copy(&file, &created).at(&created)?;
+                }
+                Err(_) => continue,
+            }
+            let extension = created.extension().unwrap_or_default();
+            if format::EXTENSIONS.iter().any(|(ext, _)| extension == *ext) {
+                notes.push(created);
+            }
+        }
+        Brain::register(data, &notes, &self.id)?;
+
+        // Open the single top level entry of the scaffold, if there is one
+        let mut top = fs::read_dir(&source)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .at(&source)?;
+        let opened = match top.len() {
+            1 => {
+                let mut name_of = top.remove(0).file_name().to_string_lossy().to_string();
+                Template::inject_variables(&mut name_of, name, target);
+                target.join(name_of)
+            }
+            _ => target.to_owned(),
+        };
+        Ok(opened)
     }
 
-    fn render_to_default(&self, pwd: PathBuf, overwrite: bool) -> Result<String, io::Error> {
-        let name: String = match &self.name {
+    /// Checks a scaffold file's path, once `$NAME` is filled in, stays below the target dir
+    fn scaffold_path(relative: &str) -> Result<PathBuf, MdbError> {
+        let path = links::normalize(Path::new(relative));
+        let below = path.components().next().is_some()
+            && path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !below {
+            return Err(MdbError::Input(format!(
+                "Scaffold file `{}` would be written outside of the target dir",
+                relative
+            )));
+        }
+        Ok(path)
+    }
+
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Template::walk(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn render_to_default(
+        &self,
+        data: &Data,
+        pwd: PathBuf,
+        overwrite: bool,
+    ) -> Result<PathBuf, MdbError> {
+        let name = self.name(data, &pwd)?;
+        self.create(data, pwd, &name, overwrite)
+    }
+
+    /// Name for a new note: the template's text, the output of its command, or a fresh id.
+    /// A `counter` id is taken from the brain even if no note gets created.
+    pub fn name(&self, data: &Data, pwd: &Path) -> Result<String, MdbError> {
+        Ok(match &self.name {
             Some(name) => match name {
                 TemplateName::Text(text) => text.to_string(),
-                TemplateName::Exec(exec) => {
-                    info!("{:?}", &exec);
-                    let command_run = Command::new(&exec.run).args(&exec.args).output().unwrap();
-                    let out = String::from_utf8_lossy(&command_run.stdout);
-                    info!("{:?}", &out);
-                    out.to_string()
-                }
+                TemplateName::Exec(exec) => exec.output()?,
+                TemplateName::Id(kind) => self.generate_id(data, *kind, pwd)?,
             },
             None => {
                 return Template::err(format!(
@@ -165,22 +691,163 @@ impl Template {
                     &self.id
                 ));
             }
-        };
-        self.create(pwd, &name, overwrite)
+        })
+    }
+
+    /// Generates ids until one doesn't name a known or existing note in the target dir
+    fn generate_id(&self, data: &Data, kind: IdKind, pwd: &Path) -> Result<String, MdbError> {
+        let brain = Brain::load(data)?;
+        let dir = self.target_dir(pwd)?;
+        let extension = self.extension(data);
+        let now = Utc::now();
+        for attempt in 0..MAX_ID_ATTEMPTS {
+            let id = match ids::generate(kind, &self.id, now, attempt) {
+                Some(id) => id,
+                None => Brain::next_counter(data, &self.id)?.to_string(),
+            };
+            let path = dir.join(format!("{}.{}", id, extension));
+            if !path.exists() && !brain.entries.contains(&path) {
+                info!("Template::generate_id {}", id);
+                return Ok(id);
+            }
+        }
+        Template::err(format!("No unique id found for template {}", self.id))
     }
 
     fn render_to_name(
         &self,
+        data: &Data,
         pwd: PathBuf,
         name: String,
         overwrite: bool,
-    ) -> Result<String, io::Error> {
-        self.create(pwd, &name, overwrite)
+    ) -> Result<PathBuf, MdbError> {
+        self.create(data, pwd, &name, overwrite)
+    }
+
+    /// Configured templates and template files in the config dir, by id
+    fn list(data: &Data) -> Result<Vec<TemplateEntry>, MdbError> {
+        let mut templates: BTreeMap<String, TemplateEntry> = data
+            .templates
+            .iter()
+            .map(|template| {
+                let entry = TemplateEntry {
+                    id: template.id.clone(),
+                    file: None,
+                    configured: true,
+                };
+                (template.id.clone(), entry)
+            })
+            .collect();
+        if let Ok(entries) = fs::read_dir(Template::config_dir()) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_note = path.extension().is_some_and(|ext| {
+                    format::EXTENSIONS
+                        .iter()
+                        .any(|(known, _)| ext.to_str() == Some(*known))
+                });
+                if is_note {
+                    if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        let entry = templates.entry(stem.to_string()).or_insert(TemplateEntry {
+                            id: stem.to_string(),
+                            file: None,
+                            configured: false,
+                        });
+                        // `get_path` picks the same file when several extensions exist
+                        entry.file.get_or_insert(Template::get_path(stem));
+                    }
+                }
+            }
+        }
+        Ok(templates.into_values().collect())
+    }
+
+    /// Copies `note` into the config dir as template `id` and appends its `[[templates]]` stanza
+    fn save(data: &Data, note: PathBuf, id: &str, placeholders: bool) -> Result<Written, MdbError> {
+        info!("Template::save {:?} as {}", note, id);
+        let mut contents = fs::read_to_string(&note).at(&note)?;
+        if placeholders {
+            let name = note.file_stem().unwrap_or_default().to_string_lossy();
+            let dir = note
+                .parent()
+                .and_then(|dir| dir.file_name())
+                .unwrap_or_default()
+                .to_string_lossy();
+            contents = Template::extract_variables(&contents, &name, &dir);
+        }
+        let extension = note
+            .extension()
+            .map_or("md".into(), |ext| ext.to_string_lossy());
+        let path = Template::install(data, id, &extension, &contents)?;
+        Ok(Written { path })
+    }
+
+    pub fn exists(data: &Data, id: &str) -> bool {
+        data.get_template(&id.to_string()).is_some() || Data::template_file_exists(id)
+    }
+
+    /// Writes a new template file and appends its `[[templates]]` stanza to the config
+    pub(crate) fn install(
+        data: &Data,
+        id: &str,
+        extension: &str,
+        contents: &str,
+    ) -> Result<PathBuf, MdbError> {
+        Template::check_id(id)?;
+        if Template::exists(data, id) {
+            return Template::err(format!("`{}` already exists", id));
+        }
+        let template = Template::config_dir().join(format!("{}.{}", id, extension));
+        fs::write(&template, contents).at(&template)?;
+
+        let config_file = Template::config_dir().join("config.toml");
+        let mut config = File::options()
+            .append(true)
+            .open(&config_file)
+            .at(&config_file)?;
+        write!(config, "{}", Template::stanza(id)).at(&config_file)?;
+        Ok(template)
+    }
+
+    /// Checks `id` is a plain file name, so its template file stays in the config dir
+    fn check_id(id: &str) -> Result<(), MdbError> {
+        let path = Path::new(id);
+        let plain = path.components().count() == 1
+            && matches!(path.components().next(), Some(Component::Normal(_)));
+        if !plain || id.contains(['/', '\\']) {
+            return Template::err(format!(
+                "`{}` is not a template id, leave out directories",
+                id
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `[[templates]]` entry appended to the config for `id`
+    fn stanza(id: &str) -> String {
+        format!("\n[[templates]]\nid = {}\n", toml::Value::from(id))
+    }
+
+    /// Reverse of `inject_variables`: swaps the note's name, today's date and
+    /// its dir name for placeholders, skipping values too short to be meaningful
+    fn extract_variables(contents: &str, name: &str, dir: &str) -> String {
+        let date = Utc::now().format("%Y-%m-%d").to_string();
+        let mut values = [(name, "$NAME"), (date.as_str(), "$DATE"), (dir, "$PWD")];
+        // Longest first, so a date inside a note's name stays part of `$NAME`
+        values.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+        let mut contents = contents.to_string();
+        for (value, placeholder) in values {
+            if value.len() >= MIN_PLACEHOLDER_LEN {
+                contents = contents.replace(value, placeholder);
+            }
+        }
+        contents
     }
 
     fn inject_variables(contents: &mut String, name: &str, path: &Path) {
         *contents = contents
             .replace("$NAME", name)
+            .replace("$ID", name)
             .replace("$DATE", &Utc::now().format("%Y-%m-%d").to_string())
             .replace(
                 "$PWD",
@@ -194,103 +861,192 @@ impl Template {
 }
 
 impl Action {
-    pub fn act(data: &Data, action: Action) -> Result<String, Box<dyn Error>> {
-        let mut pwd = env::current_dir()?;
-        return match action {
-            Action::Default(name) => Action::handle_named(name, data, pwd, false),
-            Action::New(name) => Action::handle_named(name, data, pwd, true),
-            Action::Add(name) => {
-                let error_msg = "Name must be set for `add` command.";
-                match name {
-                    Named::Name(name) => {
-                        info!("Adding {:?}", name);
-                        let name = name.expect(error_msg);
-                        pwd.push(name);
-                        pwd.set_extension("md");
-                        Brain::add(data, pwd)
-                    }
-                    _ => {
-                        return Err(Box::from(io::Error::new(
-                            io::ErrorKind::InvalidInput,
-                            error_msg,
-                        )));
-                    }
+    /// Runs the action, returning its records for `main` to print
+    pub fn act(data: &Data, action: Action) -> Result<Box<dyn Output>, MdbError> {
+        let pwd = env::current_dir()?;
+        let output: Box<dyn Output> = match action {
+            Action::Default(name) => Box::new(vec![Action::handle_named(name, data, pwd, false)?]),
+            Action::New(name) => Box::new(vec![Action::handle_named(name, data, pwd, true)?]),
+            Action::Add(paths) => {
+                let added =
+                    Brain::add(data, paths.into_iter().map(|path| pwd.join(path)).collect())?;
+                for note in &added {
+                    Hook::OnAdd.run_for(data, note.template.as_deref(), &note.path);
+                }
+                Box::new(added)
+            }
+            Action::List(archived) => Box::new(Brain::list(data, archived)?),
+            Action::Clean => {
+                let removed = Brain::clean(data)?;
+                for note in &removed {
+                    Hook::OnClean.run_for(data, note.template.as_deref(), &note.path);
+                }
+                Box::new(removed)
+            }
+            Action::Move(from, to, dry_run) => {
+                let moved = Brain::mv(data, pwd.join(from), pwd.join(to), dry_run)?;
+                if !moved.dry_run {
+                    Action::commit(data, "mv", &moved.to, &moved.paths());
                 }
+                Box::new(vec![moved])
+            }
+            Action::Remove(path, delete) => {
+                let removed = Brain::rm(data, pwd.join(path), delete)?;
+                if removed.trashed.is_some() {
+                    Action::commit(
+                        data,
+                        "rm",
+                        &removed.path,
+                        std::slice::from_ref(&removed.path),
+                    );
+                }
+                Box::new(vec![removed])
+            }
+            Action::Archive(path) => {
+                let moved = Brain::archive(data, pwd.join(path))?;
+                Action::commit(data, "archive", &moved.to, &moved.paths());
+                Box::new(vec![moved])
+            }
+            Action::Migrate(backend, to) => Box::new(vec![Brain::migrate(
+                data,
+                backend,
+                to.map(|to| pwd.join(to)),
+            )?]),
+            Action::Tasks(filter) => Box::new(Tasks::list(data, &filter)?),
+            Action::TaskDone(location) => Box::new(vec![Tasks::done(&location)?]),
+            Action::Templates => Box::new(Template::list(data)?),
+            Action::SaveTemplate(note, id, placeholders) => Box::new(vec![Template::save(
+                data,
+                pwd.join(note),
+                &id,
+                placeholders,
+            )?]),
+            Action::ExportHtml(out, tags) => {
+                Box::new(vec![Export::html(data, pwd.join(out), &tags)?])
+            }
+            Action::ImportObsidian(vault, convert) => {
+                Box::new(Import::obsidian(data, pwd.join(vault), convert)?)
+            }
+            Action::ExportJson(out, content, ndjson) => {
+                Export::json(data, out.map(|out| pwd.join(out)), content, ndjson)?
+            }
+            Action::ImportJson(file, conflict) => {
+                Box::new(Import::json(data, pwd.join(file), conflict)?)
+            }
+            Action::Search(query, archived) => Box::new(Search::notes(data, &query, archived)?),
+            Action::Serve(port) => {
+                Server::run(data, &pwd, port)?;
+                Box::new(Vec::<Written>::new())
+            }
+            Action::Lsp => {
+                Lsp::run(data)?;
+                Box::new(Vec::<Written>::new())
+            }
+            Action::Watch(format) => {
+                Watch::run(data, format)?;
+                Box::new(Vec::<Written>::new())
+            }
+            Action::History(note) => Box::new(Git::history(&pwd.join(note))?),
+            Action::Restore(note, rev) => {
+                let restored = Git::restore(&pwd.join(note), &rev)?;
+                Action::commit(
+                    data,
+                    "restore",
+                    &restored.path,
+                    std::slice::from_ref(&restored.path),
+                );
+                Box::new(vec![restored])
             }
-            Action::List => Brain::list(data),
-            Action::Clean => Brain::clean(data),
         };
+        Ok(output)
     }
 
     fn handle_named(
         name: Named,
         data: &Data,
-        mut pwd: PathBuf,
+        pwd: PathBuf,
         overwrite: bool,
-    ) -> Result<String, Box<dyn Error>> {
-        let result = match name {
-            Named::Default => match data.get_default_template() {
-                Some(tmpl) => tmpl.render_to_default(pwd, overwrite),
-                None => Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "No default template found",
-                )),
-            },
-            Named::Name(name) => {
-                let name = name.expect("Name must be set for Named::Name");
-                match data.get_default_template() {
-                    Some(tmpl) => {
-                        pwd.push(&name);
-                        pwd.push(".md");
-                        tmpl.render_to_name(pwd, name, overwrite)
-                    }
-                    None => Err(io::Error::new(
-                        io::ErrorKind::NotFound,
-                        "No default template found",
-                    )),
-                }
-            }
-            Named::Template(template_name) => {
-                let template_name =
-                    template_name.expect("Template must be set for Named::Template");
-                match data.get_template(&template_name) {
-                    Some(template) => template.render_to_default(pwd, overwrite),
-                    None => Err(io::Error::new(
-                        io::ErrorKind::NotFound,
-                        "No default template found",
-                    )),
+    ) -> Result<Created, MdbError> {
+        let (template, name) = match name {
+            Named::Default => (None, None),
+            Named::Name(name) => (None, Some(name)),
+            Named::Template(template) => (Some(template), None),
+            Named::TemplateWithName(template, name) => (Some(template), Some(name)),
+        };
+        let template = match template {
+            Some(id) => match data.get_template(&id) {
+                Some(template) => template,
+                None if Data::template_file_exists(&id) => {
+                    return Template::err(format!(
+                        "{} has no `[[templates]]` entry with id = {:?} in the config",
+                        Template::get_path(&id).display(),
+                        id
+                    ))
                 }
-            }
-            Named::TemplateWithName(templ, name) => {
-                let templ = templ.expect("Template must be set for Named::Template");
-                let name = name.expect("Name must be set for Named::Name");
-                match data.get_template(&templ) {
-                    Some(template) => template.render_to_name(pwd, name, overwrite),
-                    None => Err(io::Error::new(
-                        io::ErrorKind::NotFound,
-                        "No default template found",
-                    )),
+                None => return Template::err(format!("No template named `{}`", id)),
+            },
+            None => match data.get_default_template() {
+                Some(template) => template,
+                None => {
+                    return Template::err(
+                        "No template with id = \"default\" in the config, pick one with `-t`"
+                            .into(),
+                    )
                 }
-            }
+            },
+        };
+        let path = match name {
+            Some(name) => template.render_to_name(data, pwd, name, overwrite)?,
+            None => template.render_to_default(data, pwd, overwrite)?,
         };
+        Action::edit(data, &path)?;
+        Hook::AfterEdit.run_for(data, Some(&template.id), &path);
+        Action::commit(data, "edit", &path, std::slice::from_ref(&path));
+        Ok(Created {
+            path,
+            template: template.id.clone(),
+        })
+    }
 
-        match result {
-            Ok(result) => {
-                let editor = var("EDITOR").unwrap();
-                std::process::Command::new(editor)
-                    .arg(&result)
-                    .status()
-                    .unwrap_or_else(|_| panic!("$EDITOR must be set and able to open {}", &result));
-                Ok(result)
+    /// Commits what an action changed in a `[config.git]` root, only reporting failures
+    /// as the change itself is done
+    fn commit(data: &Data, action: &str, note: &Path, paths: &[PathBuf]) {
+        if let Err(err) = Git::commit(data, action, note, paths) {
+            eprintln!("mdb: {}", err);
+        }
+    }
+
+    /// Opens `path` in `$EDITOR`, then records it as opened and refreshes its header if it changed
+    fn edit(data: &Data, path: &Path) -> Result<(), MdbError> {
+        let editor = var("EDITOR").map_err(|_| MdbError::Editor("$EDITOR is not set".into()))?;
+        let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        let before = modified(path);
+        let status = Command::new(&editor)
+            .arg(path)
+            .status()
+            .map_err(|err| MdbError::Editor(format!("Running `{}`: {}", editor, err)))?;
+        if !status.success() {
+            return Err(MdbError::Editor(format!(
+                "`{}` {} editing {}",
+                editor,
+                status,
+                path.display()
+            )));
+        }
+        if path.is_file() {
+            Brain::opened(data, path)?;
+            if modified(path) != before {
+                Brain::touch(data, path)?;
             }
-            Err(e) => Err(Box::from(e)),
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::fs::{self, File};
+    use std::path::PathBuf;
 
     use crate::config::Named;
 
@@ -304,27 +1060,118 @@ mod tests {
     fn test_named_from_template_and_name_template() {
         assert_eq!(
             Named::from_template_and_name(Some("test".into()), None),
-            Named::Template(Some("test".into()))
+            Named::Template("test".into())
         );
     }
     #[test]
     fn test_named_from_template_and_name_name() {
         assert_eq!(
             Named::from_template_and_name(None, Some("test".into())),
-            Named::Name(Some("test".into()))
+            Named::Name("test".into())
         );
     }
     #[test]
     fn test_named_from_template_and_name_both() {
         assert_eq!(
             Named::from_template_and_name(Some("testt".into()), Some("testn".into())),
-            Named::TemplateWithName(Some("testt".into()), Some("testn".into()))
+            Named::TemplateWithName("testt".into(), "testn".into())
         );
     }
 
+    #[test]
+    fn test_extract_variables() {
+        let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        let note = format!("# standup\n{} in work, ok\n", date);
+        assert_eq!(
+            Template::extract_variables(&note, "standup", "work"),
+            "# $NAME\n$DATE in $PWD, ok\n"
+        );
+        assert_eq!(Template::extract_variables("a ok", "ok", "a"), "a ok");
+    }
+
+    fn data(templates: &str) -> Data {
+        toml::from_str(&format!("[config]\ndata = \"db.toml\"\n{}", templates)).unwrap()
+    }
+
+    #[test]
+    fn test_layout_fills_parent_blocks() {
+        let data = data(
+            r#"
+            [[templates]]
+            id = "base"
+            content = "$BLOCK(nav)|$BLOCK(content)|$BLOCK(license)"
+            blocks.license = "MIT"
+            [[templates]]
+            id = "page"
+            extends = "base"
+            content = "<$BLOCK(content)>"
+            blocks.nav = "index"
+            [[templates]]
+            id = "note"
+            extends = "page"
+            content = "body"
+            blocks.license = "CC"
+            "#,
+        );
+        data.check_templates().unwrap();
+        let note = data.get_template(&"note".into()).unwrap();
+        assert_eq!(
+            note.layout(&data, Default::default()).unwrap(),
+            "index|<body>|CC"
+        );
+    }
+
+    #[test]
+    fn test_render_does_not_write() {
+        let data = data(
+            r##"
+            [[templates]]
+            id = "meeting"
+            content = "# $NAME in $PWD\n"
+            frontmatter = { fields = ["id"] }
+            [[templates]]
+            id = "journal"
+            content = "* $NAME\n"
+            extension = "org"
+            frontmatter = { fields = ["id"] }
+            "##,
+        );
+        let meeting = data.get_template(&"meeting".into()).unwrap();
+        let pwd = std::env::temp_dir();
+        assert_eq!(
+            meeting.render(&data, &pwd, "standup-mdb-render").unwrap(),
+            format!(
+                "---\nid: \"standup-mdb-render\"\n---\n# standup-mdb-render in {}\n",
+                pwd.file_name().unwrap().to_str().unwrap()
+            )
+        );
+        assert!(!pwd.join("standup-mdb-render.md").exists());
+        let journal = data.get_template(&"journal".into()).unwrap();
+        assert_eq!(
+            journal.render(&data, &pwd, "journal-mdb-render").unwrap(),
+            "* journal-mdb-render\n"
+        );
+    }
+
+    #[test]
+    fn test_check_templates_finds_cycles() {
+        let data = data(
+            r#"
+            [[templates]]
+            id = "a"
+            extends = "b"
+            [[templates]]
+            id = "b"
+            extends = "a"
+            "#,
+        );
+        let err = data.check_templates().unwrap_err().to_string();
+        assert_eq!(err, "Config: Templates extend each other: a -> b -> a");
+    }
+
     #[test]
     fn test_template_exists_false() {
-        assert_eq!(Data::template_file_exists(&String::from("nonexist")), false)
+        assert!(!Data::template_file_exists(&String::from("nonexist")))
     }
 
     #[test]
@@ -333,8 +1180,48 @@ mod tests {
         let config_file_path = config_dir.join("test_file_exists.toml");
         fs::create_dir_all(config_dir).expect("Must have access to create config folder");
         File::create(&config_file_path).expect("Must be able to create dummy test file");
-        assert_eq!(Data::template_file_exists(&String::from("nonexist")), false);
+        assert!(!Data::template_file_exists(&String::from("nonexist")));
         fs::remove_file(&config_file_path).expect("Must be able to delete dummy test file");
-        assert_eq!(config_file_path.exists(), false);
+        assert!(!config_file_path.exists());
+    }
+
+    #[test]
+    fn test_stanza_is_toml() {
+        for id in ["daily", "日記", "say \"hi\"\\"] {
+            let config: toml::Value = toml::from_str(&Template::stanza(id)).unwrap();
+            assert_eq!(config["templates"][0]["id"].as_str(), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_scaffold_path() {
+        assert_eq!(
+            Template::scaffold_path("docs/./my project.md").unwrap(),
+            PathBuf::from("docs/my project.md")
+        );
+        assert_eq!(
+            Template::scaffold_path("a/../b.md").unwrap(),
+            PathBuf::from("b.md")
+        );
+        for relative in ["../../up.md", "a/../../up.md", "/etc/passwd", "."] {
+            assert!(Template::scaffold_path(relative).is_err(), "{}", relative);
+        }
+    }
+
+    #[test]
+    fn test_check_id() {
+        assert!(Template::check_id("daily").is_ok());
+        assert!(Template::check_id("my notes.v2").is_ok());
+        for id in [
+            "../../.bashrc",
+            "/etc/passwd",
+            "..",
+            ".",
+            "work/daily",
+            "a\\b",
+            "",
+        ] {
+            assert!(Template::check_id(id).is_err(), "{}", id);
+        }
     }
 }
@@ -0,0 +1,122 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong in mdb, by what it went wrong with
+#[derive(Debug)]
+pub enum MdbError {
+    /// Arguments that don't make sense, like a malformed `path:line`
+    Input(String),
+    /// The config file can't be read or is invalid
+    Config(String),
+    /// A template is unknown or can't be rendered
+    Template(String),
+    /// The brain can't be read or written, or doesn't allow a change
+    Brain(String),
+    /// A template's `name.exec` command couldn't run or failed
+    Exec(String),
+    /// `$EDITOR` is unset or couldn't open a note
+    Editor(String),
+    /// A git command on a note's repository failed
+    Git(String),
+    /// Notes or the brain couldn't be written out as JSON
+    Serialize(String),
+    /// Reading or writing a file
+    File(PathBuf, io::Error),
+    Io(io::Error),
+}
+
+impl MdbError {
+    /// Exit code of the process, one per kind of failure
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MdbError::File(..) | MdbError::Io(_) => 1,
+            // Same as clap's usage errors
+            MdbError::Input(_) => 2,
+            MdbError::Config(_) => 3,
+            MdbError::Template(_) => 4,
+            MdbError::Brain(_) => 5,
+            MdbError::Exec(_) => 6,
+            MdbError::Editor(_) => 7,
+            MdbError::Git(_) => 8,
+            MdbError::Serialize(_) => 9,
+        }
+    }
+}
+
+impl Display for MdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MdbError::Input(msg) => write!(f, "{}", msg),
+            MdbError::Config(msg) => write!(f, "Config: {}", msg),
+            MdbError::Template(msg) => write!(f, "Template: {}", msg),
+            MdbError::Brain(msg) => write!(f, "Brain: {}", msg),
+            MdbError::Exec(msg) => write!(f, "Name command: {}", msg),
+            MdbError::Editor(msg) => write!(f, "Editor: {}", msg),
+            MdbError::Git(msg) => write!(f, "Git: {}", msg),
+            MdbError::Serialize(msg) => write!(f, "Serializing: {}", msg),
+            MdbError::File(path, err) => write!(f, "{}: {}", path.display(), err),
+            MdbError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for MdbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MdbError::File(_, err) | MdbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MdbError {
+    fn from(err: io::Error) -> MdbError {
+        MdbError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for MdbError {
+    fn from(err: rusqlite::Error) -> MdbError {
+        MdbError::Brain(format!("SQLite: {}", err))
+    }
+}
+
+impl From<serde_json::Error> for MdbError {
+    fn from(err: serde_json::Error) -> MdbError {
+        MdbError::Serialize(err.to_string())
+    }
+}
+
+/// Names the file an io error happened on
+pub trait PathContext<T> {
+    fn at(self, path: &Path) -> Result<T, MdbError>;
+}
+
+impl<T> PathContext<T> for io::Result<T> {
+    fn at(self, path: &Path) -> Result<T, MdbError> {
+        self.map_err(|err| MdbError::File(path.to_owned(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{MdbError, PathContext};
+
+    #[test]
+    fn test_exit_code_and_context() {
+        let missing = Path::new("/nonexistent/mdb/note.md");
+        let err = fs::read_to_string(missing).at(missing).unwrap_err();
+        assert_eq!(err.exit_code(), 1);
+        assert!(err.to_string().starts_with("/nonexistent/mdb/note.md: "));
+
+        let err = MdbError::Template("No template named `nope`".to_string());
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(err.to_string(), "Template: No template named `nope`");
+    }
+}
@@ -0,0 +1,575 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use pulldown_cmark::{html, Event, Options, Parser};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    brain::{Brain, Meta},
+    config::Data,
+    error::{MdbError, PathContext},
+    format::Format,
+    frontmatter,
+    links::{self, Link, LinkKind},
+    output::{self, Output, Record, Written},
+};
+
+pub(crate) const INDEX: &str = "index.html";
+const TAGS_DIR: &str = "tags";
+const SEARCH_INDEX: &str = "search.json";
+
+/// A note being exported
+struct Page {
+    source: PathBuf,
+    /// Output file, relative to the export dir
+    out: PathBuf,
+    title: String,
+    tags: Vec<String>,
+    /// Contents without the frontmatter header
+    body: String,
+    format: Format,
+}
+
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    title: &'a str,
+    url: String,
+    tags: &'a [String],
+    text: &'a str,
+}
+
+/// One brain entry in a `mdb export json` dump
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DumpEntry {
+    /// Stored form of the path, see `Roots::portable`
+    pub path: PathBuf,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Link targets as written in the note
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(flatten)]
+    pub meta: Meta,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl Record for DumpEntry {
+    const COLUMNS: &'static [&'static str] = &[
+        "path", "title", "tags", "links", "template", "created", "opened", "archived", "content",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.path),
+            self.title.clone(),
+            self.tags.join(","),
+            self.links.join(","),
+            output::optional(&self.meta.template),
+            output::date(&self.meta.created),
+            output::date(&self.meta.opened),
+            self.meta.archived.to_string(),
+            output::optional(&self.content),
+        ]
+    }
+
+    /// One line of NDJSON
+    fn plain(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl Page {
+    /// Reads `source`, its `out` is set once all exported pages are known
+    fn read(source: &Path) -> Result<Page, MdbError> {
+        let contents = fs::read_to_string(source).at(source)?;
+        let (header, body) = frontmatter::split(&contents);
+        let format = Format::from_path(source);
+        Ok(Page {
+            source: source.to_owned(),
+            out: PathBuf::new(),
+            title: format.title(body, source),
+            tags: frontmatter::tags(&header),
+            body: body.to_string(),
+            format,
+        })
+    }
+
+    fn dir(&self) -> &Path {
+        self.out.parent().unwrap_or(Path::new(""))
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// URL of `to` from a page in `dir`, both relative to the export dir
+pub(crate) fn href(dir: &Path, to: &Path) -> String {
+    links::relative(dir, to)
+        .to_string_lossy()
+        .replace(' ', "%20")
+}
+
+/// File name of a tag's index page
+fn tag_page(tag: &str) -> PathBuf {
+    let name: String = tag
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '-',
+        })
+        .collect();
+    Path::new(TAGS_DIR).join(format!("{}.html", name))
+}
+
+/// Deepest directory containing all `paths`
+fn common_dir<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> PathBuf {
+    let mut common: Option<PathBuf> = None;
+    for path in paths {
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        common = Some(match common {
+            None => dir.to_owned(),
+            Some(mut common) => {
+                while !dir.starts_with(&common) && common.pop() {}
+                common
+            }
+        });
+    }
+    common.unwrap_or_default()
+}
+
+/// A page in `dir`, with `nav` added after the link to the index
+pub(crate) fn layout(title: &str, dir: &Path, nav: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<nav><a href=\"{}\">Index</a>{}</nav>\n<main>\n{}</main>\n</body>\n</html>\n",
+        escape(title),
+        href(dir, Path::new(INDEX)),
+        nav,
+        body
+    )
+}
+
+pub(crate) fn list<'a>(items: impl Iterator<Item = (String, &'a str)>) -> String {
+    let items: Vec<String> = items
+        .map(|(url, text)| format!("<li><a href=\"{}\">{}</a></li>", url, escape(text)))
+        .collect();
+    if items.is_empty() {
+        return String::new();
+    }
+    format!("<ul>\n{}\n</ul>\n", items.join("\n"))
+}
+
+pub struct Export {}
+impl Export {
+    /// Dumps every entry with its metadata into `out`, as a JSON array or one object per line,
+    /// or returns the entries to print
+    pub(crate) fn json(
+        data: &Data,
+        out: Option<PathBuf>,
+        content: bool,
+        ndjson: bool,
+    ) -> Result<Box<dyn Output>, MdbError> {
+        info!("Export::json {:?}", out);
+        let brain = Brain::load(data)?;
+        let mut entries = vec![];
+        for path in brain.entries.iter().collect::<BTreeSet<_>>() {
+            let contents = fs::read_to_string(path).ok();
+            let (header, body) = frontmatter::split(contents.as_deref().unwrap_or_default());
+            let format = Format::from_path(path);
+            entries.push(DumpEntry {
+                path: data.config.roots.portable(path),
+                title: format.title(body, path),
+                tags: frontmatter::tags(&header),
+                links: links::parse(body, format)
+                    .into_iter()
+                    .map(|link| link.target)
+                    .collect(),
+                meta: brain.meta.get(path).cloned().unwrap_or_default(),
+                content: contents.filter(|_| content),
+            });
+        }
+        let Some(out) = out else {
+            return Ok(Box::new(entries));
+        };
+        let dump = match ndjson {
+            true => entries
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<String>, _>>()?
+                .join("\n"),
+            false => serde_json::to_string_pretty(&entries)?,
+        };
+        fs::write(&out, format!("{}\n", dump)).at(&out)?;
+        Ok(Box::new(vec![Written { path: out }]))
+    }
+
+    /// Renders notes, or those with one of `tags`, into a static site in `out`
+    pub(crate) fn html(data: &Data, out: PathBuf, tags: &[String]) -> Result<Written, MdbError> {
+        info!("Export::html {:?} tags: {:?}", out, tags);
+        for (path, contents) in Site::build(data, tags)?.files("", true)? {
+            let file = out.join(path);
+            let dir = file.parent().unwrap_or(&out);
+            fs::create_dir_all(dir).at(dir)?;
+            fs::write(&file, contents).at(&file)?;
+        }
+        Ok(Written { path: out })
+    }
+}
+
+/// The pages of an HTML export, rendered in memory
+pub(crate) struct Site {
+    pages: Vec<Page>,
+}
+
+impl Site {
+    /// Page `link` in `page` points to, if it's exported
+    fn resolve(pages: &[Page], page: &Page, link: &Link) -> Option<usize> {
+        let dir = page.source.parent()?;
+        pages
+            .iter()
+            .position(|other| link.points_to(dir, &other.source))
+    }
+
+    /// Turns links to exported notes into links to their pages, wiki-links into markdown ones
+    fn link(pages: &[Page], page: &Page, links: &[(Link, Option<usize>)]) -> String {
+        let mut lines: Vec<String> = page.body.lines().map(String::from).collect();
+        // Right to left, so earlier ranges on the same line stay valid
+        for (link, target) in links.iter().rev() {
+            let line = &mut lines[link.line - 1];
+            let url = target.map(|target| href(page.dir(), &pages[target].out));
+            match (link.kind, url) {
+                (LinkKind::Path, Some(url)) => line.replace_range(link.range.clone(), &url),
+                (LinkKind::Wiki, url) => {
+                    let start = link.range.start - 2;
+                    let Some(end) = line[link.range.end..]
+                        .find("]]")
+                        .map(|end| link.range.end + end + 2)
+                    else {
+                        continue;
+                    };
+                    let text = match line[start + 2..end - 2].split_once('|') {
+                        Some((_, alias)) => alias.trim().to_string(),
+                        None => link.target.clone(),
+                    };
+                    let replacement = match url {
+                        Some(url) => format!("[{}]({})", text, url),
+                        None => text,
+                    };
+                    line.replace_range(start..end, &replacement);
+                }
+                _ => {}
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// HTML of `page`, with the HTML written in the note escaped unless `raw_html`
+    fn render(
+        pages: &[Page],
+        page: &Page,
+        links: &[(Link, Option<usize>)],
+        raw_html: bool,
+    ) -> String {
+        match page.format {
+            Format::Markdown => {
+                let mut rendered = String::new();
+                let linked = Site::link(pages, page, links);
+                let options = Options::ENABLE_TABLES
+                    | Options::ENABLE_FOOTNOTES
+                    | Options::ENABLE_STRIKETHROUGH
+                    | Options::ENABLE_TASKLISTS;
+                let events = Parser::new_ext(&linked, options).map(|event| match event {
+                    Event::Html(html) if !raw_html => Event::Text(html),
+                    event => event,
+                });
+                html::push_html(&mut rendered, events);
+                rendered
+            }
+            _ => format!("<pre>\n{}</pre>\n", escape(&page.body)),
+        }
+    }
+
+    /// Reads every note that isn't archived, or those with one of `tags`
+    pub(crate) fn build(data: &Data, tags: &[String]) -> Result<Site, MdbError> {
+        let brain = Brain::load(data)?;
+        let sources: BTreeSet<&PathBuf> = brain
+            .entries
+            .iter()
+            .filter(|path| !brain.is_archived(path) && path.is_file())
+            .collect();
+        let mut pages = vec![];
+        for source in sources {
+            match Page::read(source) {
+                Ok(page) if tags.is_empty() || page.tags.iter().any(|tag| tags.contains(tag)) => {
+                    pages.push(page)
+                }
+                Ok(_) => {}
+                Err(e) => info!("Site::build skipping {:?}: {}", source, e),
+            }
+        }
+        let base = common_dir(pages.iter().map(|page| &page.source));
+        for page in &mut pages {
+            let relative = page.source.strip_prefix(&base).unwrap_or(&page.source);
+            page.out = relative.with_extension("html");
+        }
+        Ok(Site { pages })
+    }
+
+    /// Page of the note `source` and its title, relative to the site's root
+    pub(crate) fn page(&self, source: &Path) -> Option<(&Path, &str)> {
+        self.pages
+            .iter()
+            .find(|page| page.source == source)
+            .map(|page| (page.out.as_path(), page.title.as_str()))
+    }
+
+    /// Every file of the site by its path, with `nav` added to each page's navigation.
+    /// Without `raw_html`, HTML in the notes is shown as text rather than run.
+    pub(crate) fn files(
+        &self,
+        nav: &str,
+        raw_html: bool,
+    ) -> Result<BTreeMap<PathBuf, String>, MdbError> {
+        let pages = &self.pages;
+        let mut files = BTreeMap::new();
+        let links: Vec<Vec<(Link, Option<usize>)>> = pages
+            .iter()
+            .map(|page| {
+                links::parse(&page.body, page.format)
+                    .into_iter()
+                    .map(|link| {
+                        let target = Site::resolve(pages, page, &link);
+                        (link, target)
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut backlinks: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); pages.len()];
+        for (from, links) in links.iter().enumerate() {
+            for target in links.iter().filter_map(|(_, target)| *target) {
+                if target != from {
+                    backlinks[target].insert(from);
+                }
+            }
+        }
+
+        for (index, page) in pages.iter().enumerate() {
+            let mut body = Site::render(pages, page, &links[index], raw_html);
+            if !page.tags.is_empty() {
+                body.push_str("<section class=\"tags\">\n<h2>Tags</h2>\n");
+                body.push_str(&list(
+                    page.tags
+                        .iter()
+                        .map(|tag| (href(page.dir(), &tag_page(tag)), tag.as_str())),
+                ));
+                body.push_str("</section>\n");
+            }
+            if !backlinks[index].is_empty() {
+                body.push_str("<section class=\"backlinks\">\n<h2>Backlinks</h2>\n");
+                body.push_str(&list(backlinks[index].iter().map(|from| {
+                    (
+                        href(page.dir(), &pages[*from].out),
+                        pages[*from].title.as_str(),
+                    )
+                })));
+                body.push_str("</section>\n");
+            }
+            files.insert(
+                page.out.clone(),
+                layout(&page.title, page.dir(), nav, &body),
+            );
+        }
+
+        // Every directory with pages below it gets an index, unless a note already renders to it
+        let mut dirs: BTreeMap<PathBuf, (BTreeSet<PathBuf>, Vec<usize>)> = BTreeMap::new();
+        dirs.entry(PathBuf::new()).or_default();
+        for (index, page) in pages.iter().enumerate() {
+            dirs.entry(page.dir().to_owned()).or_default().1.push(index);
+            for dir in page.dir().ancestors().skip(1) {
+                let child = page.dir().strip_prefix(dir).unwrap_or(page.dir());
+                let child = dir.join(child.iter().next().unwrap_or_default());
+                dirs.entry(dir.to_owned()).or_default().0.insert(child);
+            }
+        }
+        let mut tag_pages: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, page) in pages.iter().enumerate() {
+            for tag in &page.tags {
+                tag_pages.entry(tag).or_default().push(index);
+            }
+        }
+        for (dir, (subdirs, indexes)) in &dirs {
+            let index = dir.join(INDEX);
+            if pages.iter().any(|page| page.out == index) {
+                continue;
+            }
+            let title = match dir.to_str() {
+                Some("") | None => "Index",
+                Some(dir) => dir,
+            };
+            let mut body = format!("<h1>{}</h1>\n", escape(title));
+            if !subdirs.is_empty() {
+                let names: Vec<(String, String)> = subdirs
+                    .iter()
+                    .map(|subdir| {
+                        let name = subdir.file_name().unwrap_or_default();
+                        (
+                            href(dir, &subdir.join(INDEX)),
+                            format!("{}/", name.to_string_lossy()),
+                        )
+                    })
+                    .collect();
+                body.push_str(&list(
+                    names.iter().map(|(url, name)| (url.clone(), name.as_str())),
+                ));
+            }
+            body.push_str(&list(indexes.iter().map(|index| {
+                (href(dir, &pages[*index].out), pages[*index].title.as_str())
+            })));
+            if dir.as_os_str().is_empty() && !tag_pages.is_empty() {
+                body.push_str("<h2>Tags</h2>\n");
+                body.push_str(&list(
+                    tag_pages
+                        .keys()
+                        .map(|tag| (href(dir, &tag_page(tag)), *tag)),
+                ));
+            }
+            files.insert(index, layout(title, dir, nav, &body));
+        }
+
+        for (tag, indexes) in &tag_pages {
+            let dir = Path::new(TAGS_DIR);
+            let mut body = format!("<h1>#{}</h1>\n", escape(tag));
+            body.push_str(&list(indexes.iter().map(|index| {
+                (href(dir, &pages[*index].out), pages[*index].title.as_str())
+            })));
+            files.insert(tag_page(tag), layout(tag, dir, nav, &body));
+        }
+
+        let search: Vec<SearchEntry> = pages
+            .iter()
+            .map(|page| SearchEntry {
+                title: &page.title,
+                url: page.out.to_string_lossy().to_string(),
+                tags: &page.tags,
+                text: &page.body,
+            })
+            .collect();
+        let search = serde_json::to_string(&search)?;
+        files.insert(PathBuf::from(SEARCH_INDEX), search);
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{common_dir, href, tag_page, Export, Page, Site};
+    use crate::format::Format;
+    use crate::{brain::Brain, config::Data};
+
+    #[test]
+    fn test_paths() {
+        let paths = [PathBuf::from("/n/a/x.md"), PathBuf::from("/n/b/c/y.md")];
+        assert_eq!(common_dir(paths.iter()), PathBuf::from("/n"));
+        assert_eq!(
+            href(Path::new("a"), Path::new("b/c/my note.html")),
+            "../b/c/my%20note.html"
+        );
+        assert_eq!(tag_page("work/q4"), PathBuf::from("tags/work-q4.html"));
+    }
+
+    #[test]
+    fn test_raw_html() {
+        let page = Page {
+            source: PathBuf::from("/n/a.md"),
+            out: PathBuf::from("a.html"),
+            title: "a".to_string(),
+            tags: vec![],
+            body: "# A\n\n<script>alert(1)</script>\n".to_string(),
+            format: Format::Markdown,
+        };
+        let site = Site { pages: vec![page] };
+        let escaped = &site.files("", false).unwrap()[Path::new("a.html")];
+        assert!(escaped.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!escaped.contains("<script>alert"));
+        let raw = &site.files("", true).unwrap()[Path::new("a.html")];
+        assert!(raw.contains("<script>alert(1)</script>"));
+    }
+
+    #[test]
+    fn test_html_site() {
+        let dir = env::temp_dir().join(format!("mdb-export-test-{}", std::process::id()));
+        let notes = dir.join("notes");
+        fs::create_dir_all(notes.join("sub")).unwrap();
+        let write = |name: &str, contents: &str| {
+            let path = notes.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let paths = vec![
+            write(
+                "a.md",
+                "---\ntags: [work]\n---\n# A\nSee [[b]], [[missing]] and [c](sub/c.md#top)\n",
+            ),
+            write("b.md", "# B\nBack to [[a|home]]\n"),
+            write("sub/c.md", "---\ntags: [work]\n---\n# C\n[up](../a.md)\n"),
+        ];
+        let config = format!(
+            "templates = []\n[config]\ndata = {}\n",
+            toml::Value::from(dir.join("db.toml").to_str().unwrap())
+        );
+        let data: Data = toml::from_str(&config).unwrap();
+        Brain::add(&data, paths).unwrap();
+        let out = dir.join("site");
+        Export::html(&data, out.clone(), &[]).unwrap();
+        let read = |path: &str| fs::read_to_string(out.join(path)).unwrap();
+
+        let a = read("a.html");
+        assert!(a.contains("<a href=\"b.html\">b</a>"), "{}", a);
+        assert!(a.contains("missing and"), "{}", a);
+        assert!(a.contains("<a href=\"sub/c.html#top\">c</a>"), "{}", a);
+        assert!(a.contains("<a href=\"tags/work.html\">work</a>"), "{}", a);
+        let backlinks = &a[a.find("Backlinks").unwrap()..];
+        assert!(backlinks.contains("<a href=\"b.html\">B</a>"), "{}", a);
+        assert!(backlinks.contains("<a href=\"sub/c.html\">C</a>"), "{}", a);
+        assert!(read("b.html").contains("<a href=\"a.html\">home</a>"));
+        assert!(read("sub/c.html").contains("<a href=\"../a.html\">up</a>"));
+
+        let index = read("index.html");
+        assert!(
+            index.contains("<a href=\"sub/index.html\">sub/</a>"),
+            "{}",
+            index
+        );
+        assert!(
+            index.contains("<a href=\"tags/work.html\">work</a>"),
+            "{}",
+            index
+        );
+        assert!(read("sub/index.html").contains("<a href=\"c.html\">C</a>"));
+        let tag = read("tags/work.html");
+        assert!(tag.contains("<a href=\"../a.html\">A</a>"), "{}", tag);
+        assert!(tag.contains("<a href=\"../sub/c.html\">C</a>"), "{}", tag);
+
+        let search: serde_json::Value = serde_json::from_str(&read("search.json")).unwrap();
+        assert_eq!(search.as_array().unwrap().len(), 3);
+        assert_eq!(search[2]["url"], "sub/c.html");
+        assert_eq!(search[0]["tags"][0], "work");
+        assert_eq!(search[1]["text"], "# B\nBack to [[a|home]]\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::Path;
+
+/// Markup a note is written in, picked by its extension
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Markdown,
+    Org,
+    AsciiDoc,
+    Rst,
+    Text,
+}
+
+/// Every extension mdb treats as a note, the first one per format is its default
+pub const EXTENSIONS: [(&str, Format); 8] = [
+    ("md", Format::Markdown),
+    ("markdown", Format::Markdown),
+    ("org", Format::Org),
+    ("adoc", Format::AsciiDoc),
+    ("asciidoc", Format::AsciiDoc),
+    ("rst", Format::Rst),
+    ("txt", Format::Text),
+    ("text", Format::Text),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Heading {
+    /// 1-based line of the heading text
+    pub line: usize,
+    pub level: usize,
+    pub text: String,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Format {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        EXTENSIONS
+            .iter()
+            .find(|(ext, _)| Some(*ext) == extension)
+            .map_or(Format::Markdown, |(_, format)| *format)
+    }
+
+    /// Whether `line` opens or closes a literal block, whose contents are never parsed
+    pub fn is_fence(&self, line: &str) -> bool {
+        let line = line.trim();
+        match self {
+            Format::Markdown | Format::Text => line.starts_with("```"),
+            Format::Org => {
+                let line = line.to_lowercase();
+                line.starts_with("#+begin_src") || line.starts_with("#+end_src")
+            }
+            Format::AsciiDoc => line == "----" || line == "....",
+            Format::Rst => false,
+        }
+    }
+
+    /// Whether notes in this format can start with a YAML or TOML header,
+    /// Org and AsciiDoc have their own and would show one as text
+    pub fn takes_frontmatter(&self) -> bool {
+        !matches!(self, Format::Org | Format::AsciiDoc)
+    }
+
+    fn atx(line: &str, marker: char) -> Option<(usize, String)> {
+        let level = line.chars().take_while(|c| *c == marker).count();
+        let text = line[level..].strip_prefix(' ')?;
+        (level > 0).then(|| (level, text.trim().to_string()))
+    }
+
+    /// First heading of a note, else its file name without extension
+    pub fn title(&self, contents: &str, path: &Path) -> String {
+        match self.headings(contents).first() {
+            Some(heading) => heading.text.clone(),
+            None => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    pub fn headings(&self, contents: &str) -> Vec<Heading> {
+        let lines: Vec<&str> = contents.lines().collect();
+        let mut fenced = false;
+        let mut adornments: Vec<char> = vec![];
+        let mut headings = vec![];
+        for (index, line) in lines.iter().enumerate() {
+            if self.is_fence(line) {
+                fenced = !fenced;
+                continue;
+            }
+            if fenced {
+                continue;
+            }
+            let heading = match self {
+                Format::Markdown => Format::atx(line, '#'),
+                Format::Org => Format::atx(line, '*'),
+                // `= Title` is the document title at level 0, sections start at `==`
+                Format::AsciiDoc => Format::atx(line, '=').map(|(level, text)| (level - 1, text)),
+                Format::Rst => {
+                    let underline = lines.get(index + 1).unwrap_or(&"");
+                    let adornment = underline
+                        .chars()
+                        .next()
+                        .filter(|c| c.is_ascii_punctuation());
+                    match adornment {
+                        Some(adornment)
+                            if !line.trim().is_empty()
+                                && underline.chars().all(|c| c == adornment)
+                                && underline.len() >= line.trim().len() =>
+                        {
+                            if !adornments.contains(&adornment) {
+                                adornments.push(adornment);
+                            }
+                            let level =
+                                adornments.iter().position(|c| *c == adornment).unwrap_or(0);
+                            Some((level + 1, line.trim().to_string()))
+                        }
+                        _ => None,
+                    }
+                }
+                Format::Text => None,
+            };
+            if let Some((level, text)) = heading {
+                headings.push(Heading {
+                    line: index + 1,
+                    level,
+                    text,
+                });
+            }
+        }
+        headings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Format;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Format::from_path(Path::new("a.org")), Format::Org);
+        assert_eq!(Format::from_path(Path::new("a.adoc")), Format::AsciiDoc);
+        assert_eq!(Format::from_path(Path::new("a")), Format::Markdown);
+    }
+
+    #[test]
+    fn test_headings() {
+        let levels = |format: Format, contents: &str| -> Vec<(usize, String)> {
+            format
+                .headings(contents)
+                .into_iter()
+                .map(|heading| (heading.level, heading.text))
+                .collect()
+        };
+        assert_eq!(
+            levels(Format::Markdown, "# A\n```\n# no\n```\n## B"),
+            vec![(1, "A".into()), (2, "B".into())]
+        );
+        assert_eq!(
+            levels(Format::Org, "* TODO A\n** B\n#+BEGIN_SRC\n* no\n#+END_SRC"),
+            vec![(1, "TODO A".into()), (2, "B".into())]
+        );
+        assert_eq!(
+            levels(Format::AsciiDoc, "= Doc\n== A\n=== B"),
+            vec![(0, "Doc".into()), (1, "A".into()), (2, "B".into())]
+        );
+        assert_eq!(
+            levels(Format::Rst, "Title\n=====\n\nSub\n---\n"),
+            vec![(1, "Title".into()), (2, "Sub".into())]
+        );
+    }
+}
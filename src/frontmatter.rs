@@ -0,0 +1,242 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_derive::Deserialize;
+
+const YAML_FENCE: &str = "---";
+const TOML_FENCE: &str = "+++";
+const UPDATED: &str = "updated";
+
+#[derive(Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderFormat {
+    #[default]
+    Yaml,
+    Toml,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Id,
+    Created,
+    Updated,
+    Template,
+    Tags,
+}
+
+/// Header a template writes at the top of new notes, in the order `fields` are listed
+#[derive(Deserialize, Debug)]
+pub struct Frontmatter {
+    #[serde(default)]
+    format: HeaderFormat,
+    fields: Vec<Field>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Values a header is filled with
+pub struct Values<'a> {
+    pub id: &'a str,
+    pub template: &'a str,
+    pub created: DateTime<Utc>,
+}
+
+impl HeaderFormat {
+    fn fence(&self) -> &'static str {
+        match self {
+            HeaderFormat::Yaml => YAML_FENCE,
+            HeaderFormat::Toml => TOML_FENCE,
+        }
+    }
+
+    fn string(&self, value: &str) -> String {
+        match self {
+            // JSON strings are valid YAML flow scalars
+            HeaderFormat::Yaml => serde_json::Value::from(value).to_string(),
+            HeaderFormat::Toml => toml::Value::from(value).to_string(),
+        }
+    }
+
+    fn date(&self, value: DateTime<Utc>) -> String {
+        let value = value.to_rfc3339_opts(SecondsFormat::Secs, true);
+        match self {
+            HeaderFormat::Yaml => value,
+            HeaderFormat::Toml => match value.parse::<toml::value::Datetime>() {
+                Ok(date) => date.to_string(),
+                Err(_) => self.string(&value),
+            },
+        }
+    }
+
+    fn list(&self, values: &[String]) -> String {
+        let values: Vec<String> = values.iter().map(|value| self.string(value)).collect();
+        format!("[{}]", values.join(", "))
+    }
+
+    fn line(&self, key: &str, value: &str) -> String {
+        match self {
+            HeaderFormat::Yaml => format!("{}: {}", key, value),
+            HeaderFormat::Toml => format!("{} = {}", key, value),
+        }
+    }
+}
+
+impl Frontmatter {
+    pub fn render(&self, values: &Values) -> String {
+        let format = self.format;
+        let mut header = vec![format.fence().to_string()];
+        for field in &self.fields {
+            header.push(match field {
+                Field::Id => format.line("id", &format.string(values.id)),
+                Field::Created => format.line("created", &format.date(values.created)),
+                Field::Updated => format.line(UPDATED, &format.date(values.created)),
+                Field::Template => format.line("template", &format.string(values.template)),
+                Field::Tags => format.line("tags", &format.list(&self.tags)),
+            });
+        }
+        header.push(format.fence().to_string());
+        header.join("\n") + "\n"
+    }
+
+    /// Whether notes from this template keep `updated` current after editing
+    pub fn tracks_updated(&self) -> bool {
+        self.fields.contains(&Field::Updated)
+    }
+}
+
+/// `contents` with the `updated` field of its header set to `now`, if it has a header
+pub fn touch(contents: &str, now: DateTime<Utc>) -> Option<String> {
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(String::from).collect();
+    let newline = match lines.first()?.ends_with("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let format = match lines.first()?.trim_end() {
+        YAML_FENCE => HeaderFormat::Yaml,
+        TOML_FENCE => HeaderFormat::Toml,
+        _ => return None,
+    };
+    let end = 1 + lines[1..]
+        .iter()
+        .position(|line| line.trim_end() == format.fence())?;
+    let updated = format!("{}{}", format.line(UPDATED, &format.date(now)), newline);
+    let existing = lines[1..end].iter().position(|line| {
+        line.strip_prefix(UPDATED)
+            .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+    });
+    match existing {
+        Some(at) => lines[at + 1] = updated,
+        None => lines.insert(end, updated),
+    }
+    Some(lines.concat())
+}
+
+/// Header lines and the body after them, when `contents` starts with a header
+pub fn split(contents: &str) -> (Vec<&str>, &str) {
+    let mut lines = contents.split_inclusive('\n');
+    let Some(first) = lines.next() else {
+        return (vec![], contents);
+    };
+    let fence = first.trim_end();
+    if fence != YAML_FENCE && fence != TOML_FENCE {
+        return (vec![], contents);
+    }
+    let mut offset = first.len();
+    let mut header = vec![];
+    for line in lines {
+        offset += line.len();
+        if line.trim_end() == fence {
+            return (header, &contents[offset..]);
+        }
+        header.push(line.trim_end());
+    }
+    (vec![], contents)
+}
+
+/// `tags` of a header, as a `[a, "b"]` list, `- a` lines below it or a single value
+pub fn tags(header: &[&str]) -> Vec<String> {
+    let unquote = |tag: &str| tag.trim().trim_matches(['"', '\'']).to_string();
+    for (index, line) in header.iter().enumerate() {
+        let Some(value) = line
+            .strip_prefix("tags")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix([':', '=']))
+            .map(str::trim)
+        else {
+            continue;
+        };
+        let tags: Vec<String> = match value.strip_prefix('[') {
+            Some(list) => list.trim_end_matches(']').split(',').map(unquote).collect(),
+            None if value.is_empty() => header[index + 1..]
+                .iter()
+                .map_while(|line| line.trim_start().strip_prefix("- "))
+                .map(unquote)
+                .collect(),
+            None => vec![unquote(value)],
+        };
+        return tags.into_iter().filter(|tag| !tag.is_empty()).collect();
+    }
+    vec![]
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{split, tags, touch, Field, Frontmatter, HeaderFormat, Values};
+
+    #[test]
+    fn test_render() {
+        let mut frontmatter = Frontmatter {
+            format: HeaderFormat::Yaml,
+            fields: vec![Field::Id, Field::Created, Field::Template, Field::Tags],
+            tags: vec!["a".into(), "b: c".into()],
+        };
+        let values = Values {
+            id: "say \"hi\"",
+            template: "zk",
+            created: Utc.with_ymd_and_hms(2026, 10, 18, 9, 30, 0).unwrap(),
+        };
+        assert_eq!(
+            frontmatter.render(&values),
+            "---\nid: \"say \\\"hi\\\"\"\ncreated: 2026-10-18T09:30:00Z\ntemplate: \"zk\"\ntags: [\"a\", \"b: c\"]\n---\n"
+        );
+        frontmatter.format = HeaderFormat::Toml;
+        frontmatter.fields = vec![Field::Created, Field::Tags];
+        assert_eq!(
+            frontmatter.render(&values),
+            "+++\ncreated = 2026-10-18T09:30:00Z\ntags = [\"a\", \"b: c\"]\n+++\n"
+        );
+    }
+
+    #[test]
+    fn test_touch() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 8, 0, 0).unwrap();
+        assert_eq!(
+            touch("---\nid: x\nupdated: old\n---\nbody\n", now).unwrap(),
+            "---\nid: x\nupdated: 2026-10-19T08:00:00Z\n---\nbody\n"
+        );
+        assert_eq!(
+            touch("+++\nid = \"x\"\n+++\n", now).unwrap(),
+            "+++\nid = \"x\"\nupdated = 2026-10-19T08:00:00Z\n+++\n"
+        );
+        assert_eq!(
+            touch("---\r\nid: x\r\n---\r\nbody\r\n", now).unwrap(),
+            "---\r\nid: x\r\nupdated: 2026-10-19T08:00:00Z\r\n---\r\nbody\r\n"
+        );
+        assert_eq!(touch("# no header\n---\n", now), None);
+    }
+
+    #[test]
+    fn test_split_and_tags() {
+        let (header, body) = split("---\nid: x\ntags: [a, \"b c\"]\n---\n# Body\n");
+        assert_eq!(body, "# Body\n");
+        assert_eq!(tags(&header), vec!["a", "b c"]);
+        let (header, _) = split("+++\ntags = []\n+++\n");
+        assert!(tags(&header).is_empty());
+        assert_eq!(
+            tags(&["tags:", "  - a", "  - 'b'", "id: x"]),
+            vec!["a", "b"]
+        );
+        assert_eq!(split("--- not a header\n"), (vec![], "--- not a header\n"));
+    }
+}
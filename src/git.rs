@@ -0,0 +1,329 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::Utc;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    brain::Brain,
+    config::Data,
+    error::{MdbError, PathContext},
+    output::{self, Record, Written},
+};
+
+const DEFAULT_MESSAGE: &str = "mdb $ACTION $NOTE";
+/// `git log` format starting each commit with a NUL, which file names can't contain
+const LOG_FORMAT: &str = "--format=%x00%H%x09%aI%x09%s";
+const COMMIT_MARKER: char = '\0';
+
+/// `[config.git]`: roots whose notes are committed whenever mdb changes them
+#[derive(Deserialize, Debug, Default)]
+pub struct GitConfig {
+    /// Names of `[config.roots]` entries inside a git repository
+    #[serde(default)]
+    pub roots: Vec<String>,
+    /// Commit message, with `$ACTION`, `$NOTE` and `$DATE` replaced
+    pub message: Option<String>,
+}
+
+/// A commit that changed a note, as listed by `history`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Commit {
+    pub rev: String,
+    /// Author date, RFC 3339
+    pub date: String,
+    pub subject: String,
+    /// The note's path in that commit, relative to the repository
+    pub path: PathBuf,
+}
+
+impl Record for Commit {
+    const COLUMNS: &'static [&'static str] = &["rev", "date", "subject", "path"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.rev.clone(),
+            self.date.clone(),
+            self.subject.clone(),
+            output::path(&self.path),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.rev.get(..8).unwrap_or(&self.rev),
+            self.date,
+            self.subject
+        )
+    }
+}
+
+fn message(template: &str, action: &str, note: &Path) -> String {
+    template
+        .replace("$ACTION", action)
+        .replace("$NOTE", note.to_str().unwrap_or_default())
+        .replace("$DATE", &Utc::now().format("%Y-%m-%d").to_string())
+}
+
+/// Commits in `git log --name-only` output in `LOG_FORMAT`
+fn parse_log(log: &str) -> Vec<Commit> {
+    let mut commits: Vec<Commit> = vec![];
+    for line in log.lines() {
+        if let Some(header) = line.strip_prefix(COMMIT_MARKER) {
+            let mut fields = header.splitn(3, '\t');
+            commits.push(Commit {
+                rev: fields.next().unwrap_or_default().to_string(),
+                date: fields.next().unwrap_or_default().to_string(),
+                subject: fields.next().unwrap_or_default().to_string(),
+                path: PathBuf::new(),
+            });
+        } else if let Some(commit) = commits.last_mut() {
+            if !line.is_empty() && commit.path.as_os_str().is_empty() {
+                commit.path = PathBuf::from(line);
+            }
+        }
+    }
+    commits
+}
+
+pub struct Git {}
+impl Git {
+    /// Runs git in `dir`, returning its output
+    fn run(dir: &Path, args: &[&str]) -> Result<String, MdbError> {
+        info!("Git::run {:?} {:?}", dir, args);
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|err| MdbError::Git(format!("Running git: {}", err)))?;
+        if !output.status.success() {
+            return Err(MdbError::Git(format!(
+                "`git {}` in {}: {}",
+                args.join(" "),
+                dir.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Top of the git repository `dir` is in
+    fn top(dir: &Path) -> Result<PathBuf, MdbError> {
+        let top = Git::run(dir, &["rev-parse", "--show-toplevel"])?;
+        Ok(Brain::canonical(Path::new(top.trim())))
+    }
+
+    /// Top of the repository `note` is in, which may have been deleted
+    fn top_of(note: &Path) -> Result<PathBuf, MdbError> {
+        let dir = note
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.is_dir())
+            .unwrap_or(Path::new("/"));
+        Git::top(dir)
+    }
+
+    /// `path` with symlinks resolved, through its nearest existing dir once it's deleted
+    fn resolve(path: &Path) -> PathBuf {
+        if let Ok(path) = path.canonicalize() {
+            return path;
+        }
+        match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => Git::resolve(dir).join(name),
+            _ => Brain::canonical(path),
+        }
+    }
+
+    /// Top of the repository of the `[config.git]` root containing `note`, if there is one
+    fn repo(data: &Data, note: &Path) -> Result<Option<PathBuf>, MdbError> {
+        let note = Git::resolve(note);
+        let git = &data.config.git;
+        let root = data
+            .config
+            .roots
+            .dirs()
+            .into_iter()
+            .filter(|(name, _)| git.roots.iter().any(|root| root == name))
+            .map(|(_, dir)| Brain::canonical(&dir))
+            .filter(|dir| note.starts_with(dir))
+            .max_by_key(|dir| dir.components().count());
+        match root {
+            Some(root) => Ok(Some(Git::top(&root)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stages `paths` and commits them with the configured message, if `note` is below a
+    /// `[config.git]` root and any of them changed. Paths outside its repository are left out.
+    pub fn commit(
+        data: &Data,
+        action: &str,
+        note: &Path,
+        paths: &[PathBuf],
+    ) -> Result<(), MdbError> {
+        let Some(top) = Git::repo(data, note)? else {
+            return Ok(());
+        };
+        let paths: Vec<PathBuf> = paths.iter().map(|path| Git::resolve(path)).collect();
+        let relative: Vec<&str> = paths
+            .iter()
+            .filter_map(|path| path.strip_prefix(&top).ok()?.to_str())
+            .collect();
+        if relative.is_empty() {
+            return Ok(());
+        }
+        // Deleted files can only be staged if git knew them
+        let tracked = Git::run(&top, &[&["ls-files", "--"], &relative[..]].concat())?;
+        let tracked: Vec<&str> = tracked.lines().collect();
+        let stage: Vec<&str> = relative
+            .into_iter()
+            .filter(|path| top.join(path).exists() || tracked.contains(path))
+            .collect();
+        if stage.is_empty() {
+            return Ok(());
+        }
+        Git::run(&top, &[&["add", "-A", "--"], &stage[..]].concat())?;
+        let staged = Git::run(
+            &top,
+            &[&["diff", "--cached", "--name-only", "--"], &stage[..]].concat(),
+        )?;
+        if staged.trim().is_empty() {
+            return Ok(());
+        }
+        let template = data
+            .config
+            .git
+            .message
+            .as_deref()
+            .unwrap_or(DEFAULT_MESSAGE);
+        let note = Git::resolve(note);
+        let note = note.strip_prefix(&top).unwrap_or(&note);
+        let message = message(template, action, note);
+        info!("Git::commit {:?}: {}", stage, message);
+        Git::run(
+            &top,
+            &[&["commit", "--quiet", "-m", &message, "--"], &stage[..]].concat(),
+        )?;
+        Ok(())
+    }
+
+    /// Commits that changed `note`, newest first, following renames
+    pub fn history(note: &Path) -> Result<Vec<Commit>, MdbError> {
+        let note = Brain::canonical(note);
+        let top = Git::top_of(&note)?;
+        let relative = note.strip_prefix(&top).unwrap_or(&note);
+        let log = Git::run(
+            &top,
+            &[
+                "log",
+                "--follow",
+                "--name-only",
+                LOG_FORMAT,
+                "--",
+                relative.to_str().unwrap_or_default(),
+            ],
+        )?;
+        Ok(parse_log(&log))
+    }
+
+    /// Writes the version of `note` at `rev` over it, from where it was then if it was renamed
+    pub fn restore(note: &Path, rev: &str) -> Result<Written, MdbError> {
+        let note = Brain::canonical(note);
+        let top = Git::top_of(&note)?;
+        let commit = Git::run(
+            &top,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{}^{{commit}}", rev),
+            ],
+        )
+        .map_err(|_| MdbError::Input(format!("Unknown revision `{}`", rev)))?;
+        let commit = commit.trim();
+        let relative = note.strip_prefix(&top).unwrap_or(&note).to_owned();
+        let path = Git::history(&note)?
+            .into_iter()
+            .find(|found| found.rev == commit)
+            .map_or(relative, |found| found.path);
+        let contents = Git::run(
+            &top,
+            &[
+                "show",
+                &format!("{}:{}", commit, path.to_str().unwrap_or_default()),
+            ],
+        )
+        .map_err(|_| {
+            MdbError::Input(format!(
+                "{} has no version of {}, see `mdb history`",
+                rev,
+                path.display()
+            ))
+        })?;
+        info!("Git::restore {:?} from {}:{:?}", note, commit, path);
+        fs::write(&note, contents).at(&note)?;
+        Ok(Written { path: note })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{message, parse_log, Commit, Git};
+
+    #[test]
+    #[cfg(unix)]
+    fn test_resolve_symlinked_dirs() {
+        let dir = env::temp_dir().join(format!("mdb-git-test-{}", std::process::id()));
+        let real = dir.join("real");
+        fs::create_dir_all(&real).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        fs::write(real.join("a.md"), "").unwrap();
+        let real = real.canonicalize().unwrap();
+        assert_eq!(Git::resolve(&link.join("a.md")), real.join("a.md"));
+        // Deleted notes resolve through their dir
+        assert_eq!(Git::resolve(&link.join("gone.md")), real.join("gone.md"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message("notes: $ACTION $NOTE", "mv", Path::new("daily/today.md")),
+            "notes: mv daily/today.md"
+        );
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let log = "\0abc\t2026-10-18T10:00:00+02:00\tmdb mv new.md\n\nnew.md\n\0def\t2026-10-17T09:00:00+02:00\tFirst\tdraft\n\nold.md\n";
+        assert_eq!(
+            parse_log(log),
+            vec![
+                Commit {
+                    rev: "abc".to_string(),
+                    date: "2026-10-18T10:00:00+02:00".to_string(),
+                    subject: "mdb mv new.md".to_string(),
+                    path: PathBuf::from("new.md"),
+                },
+                Commit {
+                    rev: "def".to_string(),
+                    date: "2026-10-17T09:00:00+02:00".to_string(),
+                    subject: "First\tdraft".to_string(),
+                    path: PathBuf::from("old.md"),
+                },
+            ]
+        );
+    }
+}
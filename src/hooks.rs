@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use chrono::Utc;
+use log::info;
+use serde_derive::Deserialize;
+
+use crate::config::{Data, ExecCommand, Template};
+
+/// `[config.hooks]` and a template's `hooks`: commands run after mdb changed a note
+#[derive(Deserialize, Debug, Default)]
+pub struct Hooks {
+    /// After a template wrote a new note or scaffold
+    pub on_create: Option<ExecCommand>,
+    /// After `$EDITOR` exited
+    pub after_edit: Option<ExecCommand>,
+    /// After `add` put a note in the brain
+    pub on_add: Option<ExecCommand>,
+    /// After `clean` dropped a missing note from the brain
+    pub on_clean: Option<ExecCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    OnCreate,
+    AfterEdit,
+    OnAdd,
+    OnClean,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::OnCreate => "on_create",
+            Hook::AfterEdit => "after_edit",
+            Hook::OnAdd => "on_add",
+            Hook::OnClean => "on_clean",
+        }
+    }
+
+    fn command(self, hooks: &Hooks) -> Option<&ExecCommand> {
+        match self {
+            Hook::OnCreate => hooks.on_create.as_ref(),
+            Hook::AfterEdit => hooks.after_edit.as_ref(),
+            Hook::OnAdd => hooks.on_add.as_ref(),
+            Hook::OnClean => hooks.on_clean.as_ref(),
+        }
+    }
+
+    /// Variables a hook runs with: the note, its template and the template variables
+    /// `$NAME`, `$ID`, `$DATE`, `$PWD` and `$PATH`
+    fn env(
+        self,
+        template: Option<&str>,
+        note: &Path,
+        name: &str,
+        dir: &Path,
+    ) -> Vec<(String, String)> {
+        let path = |path: &Path| path.to_str().unwrap_or_default().to_string();
+        let mut env = vec![
+            ("MDB_HOOK", self.name().to_string()),
+            ("MDB_NOTE", path(note)),
+            ("MDB_NAME", name.to_string()),
+            ("MDB_ID", name.to_string()),
+            ("MDB_DATE", Utc::now().format("%Y-%m-%d").to_string()),
+            (
+                "MDB_PWD",
+                path(Path::new(dir.file_name().unwrap_or_default())),
+            ),
+            ("MDB_PATH", path(dir)),
+        ];
+        if let Some(template) = template {
+            env.push(("MDB_TEMPLATE", template.to_string()));
+        }
+        env.into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect()
+    }
+
+    /// Runs the template's hook and then the global one for `note`, created as `name` in `dir`.
+    /// Failures are only reported, as the note is already written.
+    pub fn run(
+        self,
+        data: &Data,
+        template: Option<&Template>,
+        note: &Path,
+        name: &str,
+        dir: &Path,
+    ) {
+        let hooks = template
+            .map(|template| template.hooks())
+            .into_iter()
+            .chain([&data.config.hooks]);
+        let env = self.env(template.map(|template| template.id()), note, name, dir);
+        for command in hooks.filter_map(|hooks| self.command(hooks)) {
+            info!("Hook::run {} {:?}", self.name(), note);
+            if let Err(err) = command.run_with(&env) {
+                eprintln!("mdb: Hook {} for {}: {}", self.name(), note.display(), err);
+            }
+        }
+    }
+
+    /// Runs the hook for an existing `note`, named after its file in its dir
+    pub fn run_for(self, data: &Data, template: Option<&str>, note: &Path) {
+        let template = template.and_then(|id| data.get_template(&id.to_string()));
+        let name = note.file_stem().unwrap_or_default().to_string_lossy();
+        let dir = note.parent().unwrap_or(Path::new("/"));
+        self.run(data, template, note, &name, dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Hook, Hooks};
+
+    #[test]
+    fn test_hooks_config_and_env() {
+        let hooks: Hooks = toml::from_str(
+            r#"
+            on_create = { run = "prettier", args = ["--write"] }
+            on_clean = { run = "notify-send" }
+            "#,
+        )
+        .unwrap();
+        assert!(Hook::OnCreate.command(&hooks).is_some());
+        assert!(Hook::AfterEdit.command(&hooks).is_none());
+        assert!(Hook::OnClean.command(&hooks).is_some());
+
+        let env = Hook::OnCreate.env(
+            Some("daily"),
+            Path::new("/n/work/today.md"),
+            "today",
+            Path::new("/n/work"),
+        );
+        let get = |key: &str| {
+            env.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(get("MDB_HOOK"), Some("on_create"));
+        assert_eq!(get("MDB_NOTE"), Some("/n/work/today.md"));
+        assert_eq!(get("MDB_NAME"), Some("today"));
+        assert_eq!(get("MDB_PWD"), Some("work"));
+        assert_eq!(get("MDB_PATH"), Some("/n/work"));
+        assert_eq!(get("MDB_TEMPLATE"), Some("daily"));
+    }
+}
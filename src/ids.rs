@@ -0,0 +1,72 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use ulid::Ulid;
+
+/// Crockford's base32, as used by ULIDs
+const BASE32: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const HASH_LEN: usize = 8;
+
+/// Built-in generators for unique note names
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum IdKind {
+    /// `YYYYMMDDHHmmss`
+    Timestamp,
+    Ulid,
+    /// Short base32 hash
+    Hash,
+    /// 1, 2, 3... stored per template in the brain
+    Counter,
+}
+
+/// Candidate id, `attempt` counting the ones already taken.
+/// Counters come from the brain instead.
+pub fn generate(kind: IdKind, seed: &str, now: DateTime<Utc>, attempt: u32) -> Option<String> {
+    match kind {
+        // A taken timestamp moves on to the next second
+        IdKind::Timestamp => Some(
+            (now + Duration::seconds(attempt.into()))
+                .format("%Y%m%d%H%M%S")
+                .to_string(),
+        ),
+        IdKind::Ulid => Some(Ulid::new().to_string()),
+        IdKind::Hash => {
+            let mut hasher = DefaultHasher::new();
+            let nanos = now.timestamp_subsec_nanos();
+            (seed, now.timestamp(), nanos, attempt, std::process::id()).hash(&mut hasher);
+            let hash = hasher.finish();
+            Some(
+                (0..HASH_LEN)
+                    .map(|at| BASE32[(hash >> (at * 5)) as usize & 31] as char)
+                    .collect(),
+            )
+        }
+        IdKind::Counter => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{generate, IdKind};
+
+    #[test]
+    fn test_generate() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 9, 30, 59).unwrap();
+        assert_eq!(
+            generate(IdKind::Timestamp, "zk", now, 1).unwrap(),
+            "20261018093100"
+        );
+        let hash = generate(IdKind::Hash, "zk", now, 0).unwrap();
+        assert_eq!(hash.len(), 8);
+        assert_ne!(hash, generate(IdKind::Hash, "zk", now, 1).unwrap());
+        assert_eq!(generate(IdKind::Ulid, "zk", now, 0).unwrap().len(), 26);
+        assert_eq!(generate(IdKind::Counter, "zk", now, 0), None);
+    }
+}
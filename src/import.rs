@@ -0,0 +1,466 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde_derive::Serialize;
+
+use crate::{
+    brain::{Brain, Note},
+    config::{Data, Template},
+    error::{MdbError, PathContext},
+    export::DumpEntry,
+    format::{self, Format},
+    links,
+    output::{self, Record},
+};
+
+const OBSIDIAN_DIR: &str = ".obsidian";
+/// Obsidian template variables and the mdb ones they become
+const TEMPLATE_VARIABLES: [(&str, &str); 2] = [("{{title}}", "$NAME"), ("{{date}}", "$DATE")];
+
+/// Folders set in a vault's `.obsidian` config
+#[derive(Debug, Default)]
+struct VaultConfig {
+    /// `attachmentFolderPath`: `/` for the vault root, `./dir` relative to each note,
+    /// anything else relative to the vault
+    attachments: Option<String>,
+    templates: Option<PathBuf>,
+}
+
+impl VaultConfig {
+    fn read(vault: &Path) -> VaultConfig {
+        let setting = |file: &str, key: &str| -> Option<String> {
+            let contents = fs::read_to_string(vault.join(OBSIDIAN_DIR).join(file)).ok()?;
+            let json: serde_json::Value = serde_json::from_str(&contents).ok()?;
+            json.get(key)?.as_str().map(String::from)
+        };
+        VaultConfig {
+            attachments: setting("app.json", "attachmentFolderPath"),
+            templates: setting("templates.json", "folder")
+                .map(|folder| vault.join(folder.trim_matches('/'))),
+        }
+    }
+
+    fn attachment_dir(&self, vault: &Path, note_dir: &Path) -> Option<PathBuf> {
+        let folder = self.attachments.as_deref()?;
+        Some(match folder.strip_prefix("./") {
+            Some(relative) => note_dir.join(relative),
+            None if folder == "." => note_dir.to_owned(),
+            None => vault.join(folder.trim_matches('/')),
+        })
+    }
+}
+
+struct Vault {
+    root: PathBuf,
+    config: VaultConfig,
+    notes: Vec<PathBuf>,
+    /// Everything else, e.g. attachments
+    files: Vec<PathBuf>,
+}
+
+fn is_markdown(path: &Path) -> bool {
+    let extension = path.extension().unwrap_or_default();
+    format::EXTENSIONS
+        .iter()
+        .any(|(ext, format)| *format == Format::Markdown && extension == *ext)
+}
+
+/// `[[target]]` anchors become lowercase and dashed in markdown links
+fn slug(heading: &str) -> String {
+    heading.trim().to_lowercase().replace(' ', "-")
+}
+
+impl Vault {
+    fn open(root: PathBuf) -> Result<Vault, MdbError> {
+        if !root.is_dir() {
+            return Err(MdbError::Input(format!(
+                "Vault {} not found",
+                root.display()
+            )));
+        }
+        let mut vault = Vault {
+            config: VaultConfig::read(&root),
+            root,
+            notes: vec![],
+            files: vec![],
+        };
+        let root = vault.root.clone();
+        vault.walk(&root).at(&root)?;
+        vault.notes.sort();
+        vault.files.sort();
+        Ok(vault)
+    }
+
+    /// Collects notes and other files, skipping hidden folders like `.obsidian` and the templates
+    fn walk(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden || Some(&path) == self.config.templates.as_ref() {
+                continue;
+            }
+            if path.is_dir() {
+                self.walk(&path)?;
+            } else if is_markdown(&path) {
+                self.notes.push(path);
+            } else {
+                self.files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// File a link target refers to, matched by its trailing path like Obsidian does.
+    /// Attachments in the attachments folder win over ones elsewhere.
+    fn resolve(&self, target: &str, note_dir: &Path) -> Option<PathBuf> {
+        if let Some(note) = self
+            .notes
+            .iter()
+            .find(|note| note.with_extension("").ends_with(target) || note.ends_with(target))
+        {
+            return Some(note.clone());
+        }
+        let preferred = self.config.attachment_dir(&self.root, note_dir);
+        self.files
+            .iter()
+            .filter(|file| file.ends_with(target))
+            .min_by_key(|file| {
+                preferred
+                    .as_ref()
+                    .is_none_or(|preferred| !file.starts_with(preferred))
+            })
+            .cloned()
+    }
+}
+
+/// Converts the inside of an Obsidian `[[...]]`, `embed` when written as `![[...]]`
+fn convert_link(
+    inner: &str,
+    embed: bool,
+    dir: &Path,
+    resolve: &impl Fn(&str) -> Option<PathBuf>,
+) -> String {
+    let original = format!("{}[[{}]]", if embed { "!" } else { "" }, inner);
+    let (target, alias) = match inner.split_once('|') {
+        Some((target, alias)) => (target, Some(alias.trim())),
+        None => (inner, None),
+    };
+    let (target, heading) = match target.split_once('#') {
+        Some((target, heading)) => (target.trim(), Some(heading)),
+        None => (target.trim(), None),
+    };
+    let Some(resolved) = resolve(target) else {
+        return original;
+    };
+    let mut url = links::relative(dir, &resolved)
+        .to_string_lossy()
+        .to_string();
+    if let Some(heading) = heading {
+        url = format!("{}#{}", url, slug(heading));
+    }
+    if url.contains(' ') {
+        url = format!("<{}>", url);
+    }
+    match (embed, is_markdown(&resolved), alias) {
+        // mdb doesn't transclude notes, link to them instead
+        (true, true, _) => original[1..].to_string(),
+        (true, false, alias) => {
+            // `![[image.png|300]]` sets a size, not a description
+            let alt = alias
+                .filter(|alias| !alias.chars().all(|c| c.is_ascii_digit() || c == 'x'))
+                .map(String::from)
+                .unwrap_or_else(|| {
+                    resolved
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                });
+            format!("![{}]({})", alt, url)
+        }
+        (false, _, Some(alias)) => format!("[{}]({})", alias, url),
+        (false, _, None) => original,
+    }
+}
+
+/// Rewrites Obsidian-only syntax in a note in `dir`: embeds become images or links,
+/// aliased wiki-links relative markdown links and `%%comments%%` HTML comments
+fn convert(contents: &str, dir: &Path, resolve: impl Fn(&str) -> Option<PathBuf>) -> String {
+    let mut fenced = false;
+    let mut commented = false;
+    let mut lines = vec![];
+    for line in contents.lines() {
+        if !commented && Format::Markdown.is_fence(line) {
+            fenced = !fenced;
+        }
+        if fenced || Format::Markdown.is_fence(line) {
+            lines.push(line.to_string());
+            continue;
+        }
+        let mut converted = String::new();
+        let mut rest = line;
+        loop {
+            let comment = rest.find("%%");
+            let link = match commented {
+                true => None,
+                false => rest.find("[["),
+            };
+            match (comment, link) {
+                (Some(at), link) if link.is_none_or(|link| at < link) => {
+                    converted.push_str(&rest[..at]);
+                    converted.push_str(if commented { "-->" } else { "<!--" });
+                    commented = !commented;
+                    rest = &rest[at + 2..];
+                }
+                (_, Some(at)) => {
+                    let Some(end) = rest[at..].find("]]").map(|end| at + end) else {
+                        converted.push_str(rest);
+                        break;
+                    };
+                    let embed = rest[..at].ends_with('!');
+                    converted.push_str(&rest[..at - usize::from(embed)]);
+                    converted.push_str(&convert_link(&rest[at + 2..end], embed, dir, &resolve));
+                    rest = &rest[end + 2..];
+                }
+                _ => {
+                    converted.push_str(rest);
+                    break;
+                }
+            }
+        }
+        lines.push(converted);
+    }
+    let mut converted = lines.join("\n");
+    if contents.ends_with('\n') {
+        converted.push('\n');
+    }
+    converted
+}
+
+/// What `import json` does with entries the brain already has
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Conflict {
+    /// Leave the brain's entry and file alone
+    Keep,
+    /// Take the dump's metadata, and its content when included
+    Replace,
+    /// Fill in metadata the brain doesn't have yet
+    #[default]
+    Merge,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportKind {
+    Note,
+    Template,
+}
+
+/// A note or template file `import obsidian` added
+#[derive(Serialize, Debug)]
+pub struct Imported {
+    pub kind: ImportKind,
+    pub path: PathBuf,
+}
+
+impl Record for Imported {
+    const COLUMNS: &'static [&'static str] = &["kind", "path"];
+
+    fn row(&self) -> Vec<String> {
+        let kind = match self.kind {
+            ImportKind::Note => "note",
+            ImportKind::Template => "template",
+        };
+        vec![kind.to_string(), output::path(&self.path)]
+    }
+
+    fn plain(&self) -> String {
+        output::path(&self.path)
+    }
+}
+
+pub struct Import {}
+impl Import {
+    /// Merges a dump from `export json`, read from `file` or stdin for `-`.
+    /// Included content is written for notes missing here, or all of them with `Replace`.
+    pub(crate) fn json(
+        data: &Data,
+        file: PathBuf,
+        conflict: Conflict,
+    ) -> Result<Vec<Note>, MdbError> {
+        info!("Import::json {:?} {:?}", file, conflict);
+        let mut dump = String::new();
+        match file.as_os_str() == "-" {
+            true => io::stdin().read_to_string(&mut dump)?,
+            false => File::open(&file)
+                .and_then(|mut file| file.read_to_string(&mut dump))
+                .at(&file)?,
+        };
+        let invalid = |err: serde_json::Error| {
+            MdbError::Input(format!("{} is not a dump: {}", file.display(), err))
+        };
+        let entries: Vec<DumpEntry> = match dump.trim_start().starts_with('[') {
+            true => serde_json::from_str(&dump).map_err(invalid)?,
+            false => dump
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?,
+        };
+
+        let _lock = Brain::lock(data)?;
+        let mut brain = Brain::load(data)?;
+        let mut imported = vec![];
+        for entry in entries {
+            let path = data.config.roots.resolve(&entry.path);
+            let path = match path.is_absolute() {
+                true => Brain::canonical(&path),
+                false => path,
+            };
+            let known = brain.entries.contains(&path);
+            match (known, conflict) {
+                (true, Conflict::Keep) => {}
+                (true, Conflict::Merge) => brain
+                    .meta
+                    .entry(path.clone())
+                    .or_default()
+                    .merge(&entry.meta),
+                (false, _) | (true, Conflict::Replace) => {
+                    brain.entries.insert(path.clone());
+                    match entry.meta.is_empty() {
+                        true => brain.meta.remove(&path),
+                        false => brain.meta.insert(path.clone(), entry.meta),
+                    };
+                }
+            }
+            // Paths below a root that isn't configured here stay relative, don't write those
+            if let Some(content) = entry.content.filter(|_| path.is_absolute()) {
+                let write = match path.exists() {
+                    false => true,
+                    true => {
+                        conflict == Conflict::Replace
+                            && fs::read_to_string(&path).ok().as_ref() != Some(&content)
+                    }
+                };
+                if write {
+                    info!("Import::json writing {:?}", path);
+                    let dir = path.parent().unwrap_or(Path::new("/"));
+                    fs::create_dir_all(dir).at(dir)?;
+                    fs::write(&path, content).at(&path)?;
+                }
+            }
+            if !known {
+                imported.push(Note::new(path.clone(), brain.meta.get(&path)));
+            }
+        }
+        Brain::save(data, &brain)?;
+        Ok(imported)
+    }
+
+    /// Adds every note in `vault` to the brain, and its templates to the config
+    pub(crate) fn obsidian(
+        data: &Data,
+        vault: PathBuf,
+        convert_syntax: bool,
+    ) -> Result<Vec<Imported>, MdbError> {
+        info!("Import::obsidian {:?}", vault);
+        let vault = Vault::open(Brain::canonical(&vault))?;
+        info!("Import::obsidian config {:?}", vault.config);
+
+        if convert_syntax {
+            for note in &vault.notes {
+                let dir = note.parent().unwrap_or(&vault.root);
+                let contents = fs::read_to_string(note).at(note)?;
+                let converted = convert(&contents, dir, |target| vault.resolve(target, dir));
+                if converted != contents {
+                    info!("Import::obsidian converted {:?}", note);
+                    fs::write(note, converted).at(note)?;
+                }
+            }
+        }
+        let mut imported = vec![];
+        if !vault.notes.is_empty() {
+            for note in Brain::add(data, vault.notes.clone())? {
+                imported.push(Imported {
+                    kind: ImportKind::Note,
+                    path: note.path,
+                });
+            }
+        }
+
+        let templates = match &vault.config.templates {
+            Some(templates) if templates.is_dir() => fs::read_dir(templates)
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<io::Result<Vec<PathBuf>>>()
+                })
+                .at(templates)?,
+            _ => vec![],
+        };
+        for template in templates.iter().filter(|path| is_markdown(path)) {
+            let id = template.file_stem().unwrap_or_default().to_string_lossy();
+            if Template::exists(data, &id) {
+                eprintln!("Template `{}` already exists, skipping", id);
+                continue;
+            }
+            let mut contents = fs::read_to_string(template).at(template)?;
+            for (obsidian, mdb) in TEMPLATE_VARIABLES {
+                contents = contents.replace(obsidian, mdb);
+            }
+            imported.push(Imported {
+                kind: ImportKind::Template,
+                path: Template::install(data, &id, "md", &contents)?,
+            });
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::convert;
+    use crate::{brain::Meta, export::DumpEntry};
+
+    #[test]
+    fn test_merge_dump_entry() {
+        let entry: DumpEntry = serde_json::from_str(
+            r#"{"path": "/n/a.md", "template": "daily", "created": "2024-01-02T03:04:05Z", "archived": true}"#,
+        )
+        .unwrap();
+        let mut meta = Meta {
+            template: Some("meeting".to_string()),
+            ..Meta::default()
+        };
+        meta.merge(&entry.meta);
+        assert_eq!(meta.template.as_deref(), Some("meeting"));
+        assert!(meta.created.is_some());
+        assert!(meta.archived);
+    }
+
+    #[test]
+    fn test_convert() {
+        let resolve = |target: &str| match target {
+            "Other Note" => Some(PathBuf::from("/v/sub/Other Note.md")),
+            "note" => Some(PathBuf::from("/v/note.md")),
+            "pic.png" => Some(PathBuf::from("/v/assets/pic.png")),
+            _ => None,
+        };
+        let note = "![[pic.png|300]] [[note|alias]] [[Other Note#Some Part|there]] [[note]]\n\
+                    ![[note]] [[gone|x]] %%hidden [[note|n]]\nstill%% shown\n```\n%%code%%\n```\n";
+        assert_eq!(
+            convert(note, Path::new("/v"), resolve),
+            "![pic.png](assets/pic.png) [alias](note.md) [there](<sub/Other Note.md#some-part>) [[note]]\n\
+             [[note]] [[gone|x]] <!--hidden [[note|n]]\nstill--> shown\n```\n%%code%%\n```\n"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Templates for new notes, and a brain that keeps track of them.
+//!
+//! `mdb` is a thin command line front-end over this crate:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use mdb::{Brain, Data};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let data = Data::load()?;
+//! let daily = data.get_template(&"daily".to_string()).expect("configured");
+//! let name = daily.name(&data, Path::new("."))?;
+//! println!("{}", daily.render(&data, Path::new("."), &name)?);
+//!
+//! for note in Brain::list(&data, false)? {
+//!     println!("{} {:?}", note.path.display(), note.template);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+// The library's API is `Data`, `Template` and `Brain`. The `#[doc(hidden)]` modules
+// are only public for the `mdb` binary's argument parsing.
+pub mod brain;
+pub mod config;
+pub mod error;
+mod export;
+mod format;
+mod frontmatter;
+mod git;
+mod hooks;
+mod ids;
+#[doc(hidden)]
+pub mod import;
+mod links;
+mod lsp;
+#[doc(hidden)]
+pub mod output;
+mod roots;
+mod search;
+mod serve;
+#[doc(hidden)]
+pub mod store;
+#[doc(hidden)]
+pub mod tasks;
+mod watch;
+
+pub use brain::{Brain, BrainData, Meta, Note};
+pub use config::{Data, Template};
+pub use error::MdbError;
@@ -0,0 +1,396 @@
+use std::{
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::format::Format;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LinkKind {
+    /// `[[note]]`, `[[note|alias]]` or `[[note#heading]]`, Org `[[note][desc]]`
+    Wiki,
+    /// `[text](relative/note.md)`, Org `[[file:note.org]]`, AsciiDoc `xref:note.adoc[]`
+    Path,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub target: String,
+    /// 1-based line the link is on
+    pub line: usize,
+    /// Byte range of `target` within its line
+    pub range: Range<usize>,
+}
+
+impl Link {
+    fn new(kind: LinkKind, line: &str, number: usize, range: Range<usize>) -> Option<Link> {
+        let target = &line[range.clone()];
+        if target.is_empty() || target.contains("://") {
+            return None;
+        }
+        // Wiki-style names often have spaces, paths with them aren't links
+        if kind == LinkKind::Path && target.contains(' ') {
+            return None;
+        }
+        if target.starts_with("mailto:") {
+            return None;
+        }
+        Some(Link {
+            kind,
+            target: target.to_string(),
+            line: number,
+            range,
+        })
+    }
+
+    fn wiki(line: &str, number: usize, start: usize) -> Option<(Link, usize)> {
+        let inner = start + 2;
+        let end = inner + line[inner..].find("]]")?;
+        let target_end = line[inner..end]
+            .find(['|', '#'])
+            .map_or(end, |at| inner + at);
+        let target = line[inner..target_end].trim_end();
+        let link = Link::new(LinkKind::Wiki, line, number, inner..inner + target.len())?;
+        Some((link, end + 2))
+    }
+
+    fn markdown(line: &str, number: usize, start: usize) -> Option<(Link, usize)> {
+        let inner = start + 2;
+        let end = inner + line[inner..].find(')')?;
+        let target_end = line[inner..end].find('#').map_or(end, |at| inner + at);
+        let link = Link::new(LinkKind::Path, line, number, inner..target_end)?;
+        Some((link, end + 1))
+    }
+
+    /// `[[file:note.org::search][desc]]` or `[[note][desc]]`, but not `[[*heading]]`
+    fn org(line: &str, number: usize, start: usize) -> Option<(Link, usize)> {
+        let inner = start + 2;
+        let end = inner + line[inner..].find("]]")?;
+        let target_end = line[inner..end].find("][").map_or(end, |at| inner + at);
+        let (kind, inner) = match line[inner..target_end].strip_prefix("file:") {
+            Some(_) => (LinkKind::Path, inner + "file:".len()),
+            None => (LinkKind::Wiki, inner),
+        };
+        let target_end = line[inner..target_end]
+            .find("::")
+            .map_or(target_end, |at| inner + at);
+        if line[inner..target_end].starts_with(['*', '#']) || line[inner..target_end].contains(':')
+        {
+            return None;
+        }
+        let link = Link::new(kind, line, number, inner..target_end)?;
+        Some((link, end + 2))
+    }
+
+    /// `xref:note.adoc#id[text]`, `link:note.adoc[text]` or `<<note.adoc#id,text>>`
+    fn asciidoc(line: &str, number: usize, start: usize) -> Option<(Link, usize)> {
+        let rest = &line[start..];
+        let (inner, end) = if rest.starts_with("<<") {
+            let inner = start + 2;
+            let end = inner + line[inner..].find(">>")?;
+            let target_end = line[inner..end].find(',').map_or(end, |at| inner + at);
+            // `<<id>>` points inside this document
+            if !line[inner..target_end].contains('.') {
+                return None;
+            }
+            (inner, target_end)
+        } else {
+            let inner = start + "xref:".len();
+            (inner, inner + line[inner..].find('[')?)
+        };
+        let target_end = line[inner..end].find('#').map_or(end, |at| inner + at);
+        let link = Link::new(LinkKind::Path, line, number, inner..target_end)?;
+        Some((link, end))
+    }
+
+    /// Whether this link, written in a note inside `dir`, refers to the note at `path`
+    pub fn points_to(&self, dir: &Path, path: &Path) -> bool {
+        match self.kind {
+            LinkKind::Wiki => path.with_extension("").ends_with(&self.target),
+            LinkKind::Path => normalize(&dir.join(&self.target)) == path,
+        }
+    }
+}
+
+pub fn parse_line(line: &str, number: usize, format: Format) -> Vec<Link> {
+    let mut links = vec![];
+    let mut at = 0;
+    while at < line.len() {
+        let rest = &line[at..];
+        let found = match format {
+            Format::Org if rest.starts_with("[[") => Link::org(line, number, at),
+            Format::AsciiDoc
+                if rest.starts_with("xref:")
+                    || rest.starts_with("link:")
+                    || rest.starts_with("<<") =>
+            {
+                Link::asciidoc(line, number, at)
+            }
+            // `[[id]]` is an anchor in AsciiDoc
+            Format::Org | Format::AsciiDoc => None,
+            _ if rest.starts_with("[[") => Link::wiki(line, number, at),
+            _ if rest.starts_with("](") => Link::markdown(line, number, at),
+            _ => None,
+        };
+        match found {
+            Some((link, next)) => {
+                links.push(link);
+                at = next;
+            }
+            None => at += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    links
+}
+
+/// All links in `contents`, skipping literal blocks
+pub fn parse(contents: &str, format: Format) -> Vec<Link> {
+    let mut fenced = false;
+    let mut links = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        if format.is_fence(line) {
+            fenced = !fenced;
+            continue;
+        }
+        if !fenced {
+            links.append(&mut parse_line(line, index + 1, format));
+        }
+    }
+    links
+}
+
+/// Lexically resolves `.` and `..` without touching the file system
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                // Nothing above the root, and `..` past the start of a relative path stays
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(component),
+            },
+            _ => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// Path to `to` relative to the directory `from`, both absolute
+pub fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Rewrites links to `old` into links to `new`, for a note moving from `before` to `after`.
+/// Relative markdown links to other notes are rebased when the note itself changes directory.
+/// Wiki links that also match one of `others` are left alone, and returned.
+/// Line endings are kept, so a note without such links comes back unchanged.
+pub fn rewrite(
+    contents: &str,
+    format: Format,
+    before: &Path,
+    after: &Path,
+    old: &Path,
+    new: &Path,
+    others: &[PathBuf],
+) -> (String, Vec<Link>) {
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(String::from).collect();
+    let mut ambiguous = vec![];
+    // Right to left, so earlier ranges on the same line stay valid
+    for link in parse(contents, format).iter().rev() {
+        let replacement = match link.kind {
+            LinkKind::Wiki
+                if link.points_to(before, old)
+                    && others
+                        .iter()
+                        .any(|other| other != old && link.points_to(before, other)) =>
+            {
+                ambiguous.insert(0, link.clone());
+                continue;
+            }
+            LinkKind::Wiki if link.points_to(before, old) => {
+                let depth = Path::new(&link.target).components().count();
+                let new = new.with_extension("");
+                let skip = new.components().count().saturating_sub(depth);
+                let parts: Vec<_> = new
+                    .components()
+                    .skip(skip)
+                    .map(|part| part.as_os_str().to_string_lossy().to_string())
+                    .collect();
+                parts.join("/")
+            }
+            LinkKind::Path if link.points_to(before, old) => {
+                relative(after, new).to_string_lossy().to_string()
+            }
+            LinkKind::Path if before != after => {
+                let target = normalize(&before.join(&link.target));
+                relative(after, &target).to_string_lossy().to_string()
+            }
+            _ => continue,
+        };
+        lines[link.line - 1].replace_range(link.range.clone(), &replacement);
+    }
+    (lines.concat(), ambiguous)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{normalize, parse, relative, rewrite, LinkKind};
+    use crate::format::Format;
+
+    #[test]
+    fn test_parse_links() {
+        let links = parse(
+            "[[a]] and [[b|alias]] to [c](../c.md#top) [web](https://x.y)\n```\n[[d]]\n```",
+            Format::Markdown,
+        );
+        let targets: Vec<_> = links.iter().map(|link| link.target.as_str()).collect();
+        assert_eq!(targets, vec!["a", "b", "../c.md"]);
+
+        assert_eq!(links[2].kind, LinkKind::Path);
+
+        let spaced = parse("[[My Note|it]] and [not](a link.md)", Format::Markdown);
+        assert_eq!(spaced.len(), 1);
+        assert_eq!(spaced[0].target, "My Note");
+    }
+
+    #[test]
+    fn test_parse_org_and_asciidoc_links() {
+        let org = parse(
+            "[[file:sub/a.org::*Top][A]] [[b][B]] [[*Heading]] [[https://x.y][web]]",
+            Format::Org,
+        );
+        let targets: Vec<_> = org
+            .iter()
+            .map(|link| (link.kind, link.target.as_str()))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![(LinkKind::Path, "sub/a.org"), (LinkKind::Wiki, "b")]
+        );
+
+        let adoc = parse(
+            "[[anchor]] xref:a.adoc#s[A], <<b.adoc#,B>> <<local>> link:https://x.y[web]",
+            Format::AsciiDoc,
+        );
+        let targets: Vec<_> = adoc.iter().map(|link| link.target.as_str()).collect();
+        assert_eq!(targets, vec!["a.adoc", "b.adoc"]);
+    }
+
+    #[test]
+    fn test_normalize_and_relative() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(
+            relative(Path::new("/a/b"), Path::new("/a/c/d.md")),
+            PathBuf::from("../c/d.md")
+        );
+    }
+
+    #[test]
+    fn test_rewrite_other_note() {
+        let dir = Path::new("/n");
+        let note = "see [[old]], [[old|this]] and [it](sub/old.md#x)\n";
+        assert_eq!(
+            rewrite(
+                note,
+                Format::Markdown,
+                dir,
+                dir,
+                Path::new("/n/sub/old.md"),
+                Path::new("/n/new.md"),
+                &[]
+            )
+            .0,
+            "see [[new]], [[new|this]] and [it](new.md#x)\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_keeps_line_endings() {
+        let dir = Path::new("/n");
+        let rewrite = |note: &str| {
+            rewrite(
+                note,
+                Format::Markdown,
+                dir,
+                dir,
+                Path::new("/n/old.md"),
+                Path::new("/n/new.md"),
+                &[],
+            )
+            .0
+        };
+        assert_eq!(rewrite("a\r\nsee [[old]]\r\n"), "a\r\nsee [[new]]\r\n");
+        assert_eq!(rewrite("no links\r\nhere"), "no links\r\nhere");
+    }
+
+    #[test]
+    fn test_rewrite_names_with_spaces() {
+        let dir = Path::new("/n");
+        assert_eq!(
+            rewrite(
+                "see [[My Note]] and [[My Note#Top|this]]\n",
+                Format::Markdown,
+                dir,
+                dir,
+                Path::new("/n/My Note.md"),
+                Path::new("/n/Your Note.md"),
+                &[]
+            )
+            .0,
+            "see [[Your Note]] and [[Your Note#Top|this]]\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_skips_ambiguous_wiki_links() {
+        let dir = Path::new("/n");
+        let others = [PathBuf::from("/n/a/old.md"), PathBuf::from("/n/b/old.md")];
+        let (rewritten, ambiguous) = rewrite(
+            "[[old]] and [[a/old]]\n",
+            Format::Markdown,
+            dir,
+            dir,
+            Path::new("/n/a/old.md"),
+            Path::new("/n/a/new.md"),
+            &others,
+        );
+        assert_eq!(rewritten, "[[old]] and [[a/new]]\n");
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous[0].target, "old");
+    }
+
+    #[test]
+    fn test_rewrite_moved_note_rebases() {
+        let note = "[other](other.md)";
+        assert_eq!(
+            rewrite(
+                note,
+                Format::Markdown,
+                Path::new("/n"),
+                Path::new("/n/archive"),
+                Path::new("/n/old.md"),
+                Path::new("/n/archive/old.md"),
+                &[]
+            )
+            .0,
+            "[other](../other.md)"
+        );
+    }
+}
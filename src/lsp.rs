@@ -0,0 +1,500 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{
+        CodeActionRequest, Completion, ExecuteCommand, GotoDefinition, HoverRequest, References,
+        Request as LspRequest,
+    },
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Command, CompletionItem, CompletionItemKind, CompletionOptions,
+    CompletionParams, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, Documentation, ExecuteCommandOptions, ExecuteCommandParams,
+    GotoDefinitionParams, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+use serde_json::Value;
+
+use crate::{
+    brain::Brain,
+    config::Data,
+    error::MdbError,
+    format::Format,
+    frontmatter,
+    links::{self, Link},
+};
+
+/// Command run by the code action creating a missing note, with the template, dir and name
+const CREATE: &str = "mdb.create";
+/// Lines of a linked note shown on hover
+const HOVER_LINES: usize = 20;
+
+/// Byte offset in `line` of an LSP position's UTF-16 `character`
+fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (at, c) in line.char_indices() {
+        if units >= character as usize {
+            return at;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// UTF-16 character of the byte offset `at` in `line`
+fn character(line: &str, at: usize) -> u32 {
+    line[..at].encode_utf16().count() as u32
+}
+
+/// LSP range of a link's target
+fn range(contents: &str, link: &Link) -> Range {
+    let line = contents.lines().nth(link.line - 1).unwrap_or_default();
+    let number = (link.line - 1) as u32;
+    Range::new(
+        Position::new(number, character(line, link.range.start)),
+        Position::new(number, character(line, link.range.end)),
+    )
+}
+
+/// The link under `position`, including its brackets
+fn link_at(contents: &str, format: Format, position: Position) -> Option<Link> {
+    let line = contents.lines().nth(position.line as usize)?;
+    let at = byte_offset(line, position.character);
+    links::parse_line(line, position.line as usize + 1, format)
+        .into_iter()
+        .find(|link| link.range.start.saturating_sub(2) <= at && at <= link.range.end + 2)
+}
+
+/// Byte offset after an unclosed `[[` before `position`, and whether `]]` follows it
+fn wiki_prefix(contents: &str, position: Position) -> Option<(usize, bool)> {
+    let line = contents
+        .lines()
+        .nth(position.line as usize)
+        .unwrap_or_default();
+    let at = byte_offset(line, position.character);
+    let start = line[..at].rfind("[[")? + 2;
+    if line[start..at].contains("]]") {
+        return None;
+    }
+    Some((start, line[at..].starts_with("]]")))
+}
+
+fn protocol(err: impl Display) -> MdbError {
+    MdbError::Io(io::Error::other(err.to_string()))
+}
+
+fn cast<R: LspRequest>(request: &Request) -> Option<R::Params> {
+    match request.method == R::METHOD {
+        true => serde_json::from_value(request.params.clone()).ok(),
+        false => None,
+    }
+}
+
+/// Language server over stdio for notes tracked in the brain
+pub struct Lsp<'a> {
+    data: &'a Data,
+    connection: Connection,
+    /// Contents of the documents open in the editor
+    documents: HashMap<Url, String>,
+}
+
+impl<'a> Lsp<'a> {
+    /// Serves requests until the editor shuts the server down
+    pub(crate) fn run(data: &Data) -> Result<(), MdbError> {
+        let (connection, io_threads) = Connection::stdio();
+        let capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec!["[".to_string()]),
+                ..CompletionOptions::default()
+            }),
+            definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands: vec![CREATE.to_string()],
+                ..ExecuteCommandOptions::default()
+            }),
+            ..ServerCapabilities::default()
+        };
+        let capabilities = serde_json::to_value(capabilities).map_err(io::Error::from)?;
+        connection.initialize(capabilities).map_err(protocol)?;
+        info!("Lsp::run initialized");
+        let mut lsp = Lsp {
+            data,
+            connection,
+            documents: HashMap::new(),
+        };
+        lsp.serve()?;
+        // The writer thread only finishes once the connection is gone
+        drop(lsp);
+        io_threads.join()?;
+        Ok(())
+    }
+
+    fn serve(&mut self) -> Result<(), MdbError> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self
+                        .connection
+                        .handle_shutdown(&request)
+                        .map_err(protocol)?
+                    {
+                        return Ok(());
+                    }
+                    let id = request.id.clone();
+                    let response = match self.request(&request) {
+                        Ok(Some(result)) => Response::new_ok(id, result),
+                        Ok(None) => Response::new_err(
+                            id,
+                            ErrorCode::MethodNotFound as i32,
+                            format!("Unsupported request {}", request.method),
+                        ),
+                        Err(err) => {
+                            info!("Lsp::serve {} failed: {}", request.method, err);
+                            Response::new_err(id, ErrorCode::RequestFailed as i32, err.to_string())
+                        }
+                    };
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => {
+                    let method = notification.method.clone();
+                    if let Err(err) = self.notification(notification) {
+                        info!("Lsp::serve {} failed: {}", method, err);
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<(), MdbError> {
+        self.connection.sender.send(message).map_err(protocol)
+    }
+
+    /// The result of `request`, `None` if it isn't supported
+    fn request(&mut self, request: &Request) -> Result<Option<Value>, MdbError> {
+        info!("Lsp::request {}", request.method);
+        let result = if let Some(params) = cast::<Completion>(request) {
+            serde_json::to_value(self.completion(params)?)
+        } else if let Some(params) = cast::<GotoDefinition>(request) {
+            serde_json::to_value(self.definition(params)?)
+        } else if let Some(params) = cast::<References>(request) {
+            serde_json::to_value(self.references(params)?)
+        } else if let Some(params) = cast::<HoverRequest>(request) {
+            serde_json::to_value(self.hover(params)?)
+        } else if let Some(params) = cast::<CodeActionRequest>(request) {
+            serde_json::to_value(self.code_actions(params)?)
+        } else if let Some(params) = cast::<ExecuteCommand>(request) {
+            self.execute(params)?;
+            Ok(Value::Null)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(result.map_err(io::Error::from)?))
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<(), MdbError> {
+        let method = notification.method.as_str();
+        info!("Lsp::notification {}", method);
+        let params = notification.params;
+        let uri = if method == DidOpenTextDocument::METHOD {
+            let params: DidOpenTextDocumentParams =
+                serde_json::from_value(params).map_err(protocol)?;
+            let uri = params.text_document.uri;
+            self.documents
+                .insert(uri.clone(), params.text_document.text);
+            uri
+        } else if method == DidChangeTextDocument::METHOD {
+            let params: DidChangeTextDocumentParams =
+                serde_json::from_value(params).map_err(protocol)?;
+            let uri = params.text_document.uri;
+            // Full sync, so the last change holds the whole document
+            if let Some(change) = params.content_changes.into_iter().last() {
+                self.documents.insert(uri.clone(), change.text);
+            }
+            uri
+        } else if method == DidSaveTextDocument::METHOD {
+            let params: DidSaveTextDocumentParams =
+                serde_json::from_value(params).map_err(protocol)?;
+            params.text_document.uri
+        } else if method == DidCloseTextDocument::METHOD {
+            let params: DidCloseTextDocumentParams =
+                serde_json::from_value(params).map_err(protocol)?;
+            self.documents.remove(&params.text_document.uri);
+            return self.publish(params.text_document.uri, vec![]);
+        } else {
+            return Ok(());
+        };
+        let diagnostics = self.diagnostics(&uri)?;
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<(), MdbError> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        self.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+
+    /// Path and contents of a document, open in the editor or on disk
+    fn document(&self, uri: &Url) -> Result<(PathBuf, String), MdbError> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| MdbError::Input(format!("{} is not a file", uri)))?;
+        let contents = match self.documents.get(uri) {
+            Some(contents) => contents.clone(),
+            None => fs::read_to_string(&path).unwrap_or_default(),
+        };
+        Ok((path, contents))
+    }
+
+    /// Known notes that exist and aren't archived
+    fn notes(&self) -> Result<BTreeSet<PathBuf>, MdbError> {
+        let brain = Brain::load(self.data)?;
+        Ok(brain
+            .entries
+            .iter()
+            .filter(|path| !brain.is_archived(path) && path.is_file())
+            .cloned()
+            .collect())
+    }
+
+    /// Note `link`, written in a note inside `dir`, points to: a known note or an existing file
+    fn resolve(notes: &BTreeSet<PathBuf>, dir: &Path, link: &Link) -> Option<PathBuf> {
+        if let Some(note) = notes.iter().find(|note| link.points_to(dir, note)) {
+            return Some(note.clone());
+        }
+        let path = links::normalize(&dir.join(&link.target));
+        path.exists().then_some(path)
+    }
+
+    fn diagnostics(&self, uri: &Url) -> Result<Vec<Diagnostic>, MdbError> {
+        let (path, contents) = self.document(uri)?;
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        let notes = self.notes()?;
+        Ok(links::parse(&contents, Format::from_path(&path))
+            .into_iter()
+            .filter(|link| Lsp::resolve(&notes, dir, link).is_none())
+            .map(|link| Diagnostic {
+                range: range(&contents, &link),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("mdb".to_string()),
+                message: format!("No note `{}`", link.target),
+                ..Diagnostic::default()
+            })
+            .collect())
+    }
+
+    /// Known notes after an unclosed `[[`, matched on their name and title
+    fn completion(&self, params: CompletionParams) -> Result<Vec<CompletionItem>, MdbError> {
+        let position = params.text_document_position.position;
+        let (path, contents) = self.document(&params.text_document_position.text_document.uri)?;
+        if Format::from_path(&path) == Format::AsciiDoc {
+            return Ok(vec![]);
+        }
+        let Some((start, closed)) = wiki_prefix(&contents, position) else {
+            return Ok(vec![]);
+        };
+        let line = contents
+            .lines()
+            .nth(position.line as usize)
+            .unwrap_or_default();
+        let replace = Range::new(
+            Position::new(position.line, character(line, start)),
+            position,
+        );
+        let mut items = vec![];
+        for note in self.notes()? {
+            let Some(name) = note
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            let note_contents = fs::read_to_string(&note).unwrap_or_default();
+            let (_, body) = frontmatter::split(&note_contents);
+            let title = Format::from_path(&note).title(body, &note);
+            let new_text = match closed {
+                true => name.clone(),
+                false => format!("{}]]", name),
+            };
+            items.push(CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::FILE),
+                detail: Some(title.clone()),
+                documentation: Some(Documentation::String(note.display().to_string())),
+                filter_text: Some(format!("{} {}", name, title)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(replace, new_text))),
+                ..CompletionItem::default()
+            });
+        }
+        Ok(items)
+    }
+
+    /// Link under the cursor and the note it points to
+    fn target(&self, uri: &Url, position: Position) -> Result<Option<(Range, PathBuf)>, MdbError> {
+        let (path, contents) = self.document(uri)?;
+        let Some(link) = link_at(&contents, Format::from_path(&path), position) else {
+            return Ok(None);
+        };
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        Ok(Lsp::resolve(&self.notes()?, dir, &link).map(|note| (range(&contents, &link), note)))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Result<Option<Location>, MdbError> {
+        let position = params.text_document_position_params;
+        Ok(self
+            .target(&position.text_document.uri, position.position)?
+            .and_then(|(_, note)| Url::from_file_path(note).ok())
+            .map(|uri| Location::new(uri, Range::default())))
+    }
+
+    /// Links in known notes pointing to the document
+    fn references(&self, params: ReferenceParams) -> Result<Vec<Location>, MdbError> {
+        let (path, _) = self.document(&params.text_document_position.text_document.uri)?;
+        let mut locations = vec![];
+        for note in self.notes()? {
+            let Ok(uri) = Url::from_file_path(&note) else {
+                continue;
+            };
+            let (_, contents) = self.document(&uri)?;
+            let dir = note.parent().unwrap_or(Path::new("/"));
+            for link in links::parse(&contents, Format::from_path(&note)) {
+                if link.points_to(dir, &path) {
+                    locations.push(Location::new(uri.clone(), range(&contents, &link)));
+                }
+            }
+        }
+        Ok(locations)
+    }
+
+    /// The start of the linked note
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>, MdbError> {
+        let position = params.text_document_position_params;
+        let Some((range, note)) = self.target(&position.text_document.uri, position.position)?
+        else {
+            return Ok(None);
+        };
+        let contents = fs::read_to_string(&note).unwrap_or_default();
+        let (_, body) = frontmatter::split(&contents);
+        let preview: Vec<&str> = body.lines().take(HOVER_LINES).collect();
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: preview.join("\n"),
+            }),
+            range: Some(range),
+        }))
+    }
+
+    /// Creating the missing note of a broken link in the range, from each template
+    fn code_actions(&self, params: CodeActionParams) -> Result<Vec<CodeActionOrCommand>, MdbError> {
+        let (path, contents) = self.document(&params.text_document.uri)?;
+        let Some(link) = link_at(&contents, Format::from_path(&path), params.range.start) else {
+            return Ok(vec![]);
+        };
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        if Lsp::resolve(&self.notes()?, dir, &link).is_some() {
+            return Ok(vec![]);
+        }
+        let target = links::normalize(&dir.join(&link.target));
+        let (Some(target_dir), Some(name)) = (target.parent(), target.file_stem()) else {
+            return Ok(vec![]);
+        };
+        let name = name.to_string_lossy().to_string();
+        Ok(self
+            .data
+            .templates
+            .iter()
+            .map(|template| {
+                let title = format!("Create `{}` from template `{}`", name, template.id());
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: title.clone(),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    command: Some(Command::new(
+                        title,
+                        CREATE.to_string(),
+                        Some(vec![
+                            Value::from(template.id()),
+                            Value::from(target_dir.to_string_lossy()),
+                            Value::from(name.as_str()),
+                        ]),
+                    )),
+                    ..CodeAction::default()
+                })
+            })
+            .collect())
+    }
+
+    /// Creates the note of a code action, keeping an existing file, and refreshes diagnostics
+    fn execute(&self, params: ExecuteCommandParams) -> Result<(), MdbError> {
+        let arguments: Vec<&str> = params.arguments.iter().filter_map(Value::as_str).collect();
+        let [id, dir, name] = arguments[..] else {
+            return Err(MdbError::Input(format!(
+                "{} expects a template, dir and name, got {:?}",
+                params.command, params.arguments
+            )));
+        };
+        let template = self
+            .data
+            .get_template(&id.to_string())
+            .ok_or_else(|| MdbError::Template(format!("No template named `{}`", id)))?;
+        let path = template.create(self.data, PathBuf::from(dir), name, false)?;
+        info!("Lsp::execute created {:?}", path);
+        for uri in self.documents.keys() {
+            self.publish(uri.clone(), self.diagnostics(uri)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+
+    use crate::format::Format;
+
+    use super::{byte_offset, character, link_at, wiki_prefix};
+
+    #[test]
+    fn test_positions_are_utf16() {
+        let line = "é😀 [[note]]";
+        assert_eq!(byte_offset(line, 3), "é😀".len());
+        assert_eq!(character(line, "é😀".len()), 3);
+        assert_eq!(byte_offset(line, 100), line.len());
+    }
+
+    #[test]
+    fn test_link_at_and_wiki_prefix() {
+        let contents = "# Note\nSee [[beta|b]] and [x](sub/x.md)\nOpen [[be";
+        let link = link_at(contents, Format::Markdown, Position::new(1, 6)).unwrap();
+        assert_eq!(link.target, "beta");
+        let link = link_at(contents, Format::Markdown, Position::new(1, 28)).unwrap();
+        assert_eq!(link.target, "sub/x.md");
+        assert!(link_at(contents, Format::Markdown, Position::new(0, 2)).is_none());
+
+        assert_eq!(wiki_prefix(contents, Position::new(2, 9)), Some((7, false)));
+        assert_eq!(wiki_prefix(contents, Position::new(1, 17)), None);
+    }
+}
@@ -1,17 +1,30 @@
-mod brain;
-mod config;
 mod log;
 
-use crate::config::{Action, Data, Named, Template};
 use crate::log::init_log;
 use ::log::{info, LevelFilter};
+use chrono::NaiveDate;
 use clap::{arg, command, ArgAction, Command};
-use core::panic;
-use std::error::Error;
-use std::fs::{self, File};
-use std::io::prelude::*;
+use mdb::config::{Action, Data, Named};
+use mdb::import::Conflict;
+use mdb::output::OutputFormat;
+use mdb::store::Backend;
+use mdb::tasks::{Status, TaskFilter};
+use mdb::MdbError;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("mdb: {}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), MdbError> {
     let cli_result = init_cli();
     let log_filter = match cli_result
         .get_one::<u8>("debug")
@@ -23,58 +36,197 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
     init_log(log_filter).expect("Logging must be successfully initialized");
 
-    // Initialize config directory and prepare config file
-    let config_file_path = Template::config_dir().join("config.toml");
-    info!("{:?}", config_file_path);
-    fs::create_dir_all(Template::config_dir()).expect("Must have access to create config folder");
-    let mut file = if config_file_path.exists() {
-        File::open(config_file_path).unwrap()
-    } else {
-        //TODO: fill with default config
-        File::create(config_file_path).unwrap()
-    };
-
-    // Read config file
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let data: Data = toml::from_str(&contents)?;
+    // Read the config file, creating it on first run
+    let data = Data::load()?;
 
     info!("Config: {:?}", data.config);
     info!("Templates: {:?}", data.templates);
 
+    let format = cli_result
+        .get_one::<String>("format")
+        .and_then(|format| OutputFormat::from_name(format))
+        .unwrap_or_default();
     let action: Action;
     let mut name: Option<String> = None;
     let mut template: Option<String> = None;
 
     // Parse out name and template
     // Then set the action to the corresponding type
-    parse_template_arg(&cli_result, &mut template)?;
+    parse_template_arg(&data, &cli_result, &mut template)?;
     parse_name_arg(&data, &cli_result, &mut template, &mut name);
     if let Some(matches) = cli_result.subcommand_matches("new") {
-        parse_template_arg(matches, &mut template)?;
+        parse_template_arg(&data, matches, &mut template)?;
         parse_name_arg(&data, matches, &mut template, &mut name);
         action = Action::New(Named::from_template_and_name(template, name));
     } else if let Some(matches) = cli_result.subcommand_matches("add") {
-        parse_name_arg(&data, matches, &mut template, &mut name);
-        action = Action::Add(Named::from_template_and_name(template, name));
-    } else if cli_result.subcommand_matches("list").is_some() {
-        action = Action::List;
+        action = Action::Add(
+            matches
+                .get_many::<String>("paths")
+                .expect("Paths are required")
+                .map(PathBuf::from)
+                .collect(),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("templates") {
+        action = match matches.subcommand_matches("save") {
+            Some(save) => Action::SaveTemplate(
+                save.get_one::<String>("note")
+                    .expect("Note is required")
+                    .into(),
+                save.get_one::<String>("id")
+                    .expect("Id is required")
+                    .to_owned(),
+                save.get_flag("placeholders"),
+            ),
+            None => Action::Templates,
+        };
+    } else if let Some(matches) = cli_result.subcommand_matches("list") {
+        action = Action::List(matches.get_flag("archived"));
     } else if cli_result.subcommand_matches("clean").is_some() {
         action = Action::Clean;
+    } else if let Some(matches) = cli_result.subcommand_matches("mv") {
+        action = Action::Move(
+            matches
+                .get_one::<String>("from")
+                .expect("From is required")
+                .into(),
+            matches
+                .get_one::<String>("to")
+                .expect("To is required")
+                .into(),
+            matches.get_flag("dry-run"),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("rm") {
+        action = Action::Remove(
+            matches
+                .get_one::<String>("note")
+                .expect("Note is required")
+                .into(),
+            matches.get_flag("delete"),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("history") {
+        action = Action::History(
+            matches
+                .get_one::<String>("note")
+                .expect("Note is required")
+                .into(),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("restore") {
+        action = Action::Restore(
+            matches
+                .get_one::<String>("note")
+                .expect("Note is required")
+                .into(),
+            matches
+                .get_one::<String>("rev")
+                .expect("Rev is required")
+                .to_owned(),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("archive") {
+        action = Action::Archive(
+            matches
+                .get_one::<String>("note")
+                .expect("Note is required")
+                .into(),
+        );
+    } else if let Some(matches) = cli_result
+        .subcommand_matches("db")
+        .and_then(|db| db.subcommand_matches("migrate"))
+    {
+        let to = matches.get_one::<String>("to").expect("To is required");
+        action = Action::Migrate(
+            Backend::from_name(to).expect("Backends are validated by clap"),
+            matches.get_one::<String>("out").map(PathBuf::from),
+        );
+    } else if let Some(matches) = cli_result
+        .subcommand_matches("export")
+        .and_then(|export| export.subcommand_matches("json"))
+    {
+        action = Action::ExportJson(
+            matches.get_one::<String>("out").map(PathBuf::from),
+            matches.get_flag("content"),
+            matches.get_flag("ndjson"),
+        );
+    } else if let Some(matches) = cli_result
+        .subcommand_matches("import")
+        .and_then(|import| import.subcommand_matches("json"))
+    {
+        let conflict = match matches.get_one::<String>("conflict").map(String::as_str) {
+            Some("keep") => Conflict::Keep,
+            Some("replace") => Conflict::Replace,
+            _ => Conflict::Merge,
+        };
+        action = Action::ImportJson(
+            matches
+                .get_one::<String>("file")
+                .expect("File is required")
+                .into(),
+            conflict,
+        );
+    } else if let Some(matches) = cli_result
+        .subcommand_matches("export")
+        .and_then(|export| export.subcommand_matches("html"))
+    {
+        action = Action::ExportHtml(
+            matches
+                .get_one::<String>("outdir")
+                .expect("Outdir is required")
+                .into(),
+            matches
+                .get_many::<String>("tag")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+        );
+    } else if let Some(matches) = cli_result
+        .subcommand_matches("import")
+        .and_then(|import| import.subcommand_matches("obsidian"))
+    {
+        action = Action::ImportObsidian(
+            matches
+                .get_one::<String>("vault")
+                .expect("Vault is required")
+                .into(),
+            matches.get_flag("convert"),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("search") {
+        action = Action::Search(
+            matches
+                .get_many::<String>("query")
+                .expect("Query is required")
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" "),
+            matches.get_flag("archived"),
+        );
+    } else if let Some(matches) = cli_result.subcommand_matches("serve") {
+        action = Action::Serve(*matches.get_one::<u16>("port").expect("Port is defaulted"));
+    } else if cli_result.subcommand_matches("lsp").is_some() {
+        action = Action::Lsp;
+    } else if cli_result.subcommand_matches("watch").is_some() {
+        action = Action::Watch(format);
+    } else if let Some(matches) = cli_result.subcommand_matches("tasks") {
+        action = match matches.subcommand_matches("done") {
+            Some(done) => Action::TaskDone(
+                done.get_one::<String>("location")
+                    .expect("Location is required")
+                    .to_owned(),
+            ),
+            None => Action::Tasks(parse_task_filter(matches)?),
+        };
     } else {
         action = Action::Default(Named::from_template_and_name(template, name));
     }
 
     info!("{:?}", action);
 
-    // Act on the action
-    match Action::act(&data, action) {
-        Ok(ok) => {
-            info!("{:?}", ok);
-            Ok(())
-        }
-        Err(e) => Err(e),
+    // Act on the action, and print its result
+    let output = Action::act(&data, action)?
+        .render(format)
+        .map_err(io::Error::from)?;
+    if !output.is_empty() {
+        println!("{}", output);
     }
+    Ok(())
 }
 
 fn init_cli() -> clap::ArgMatches {
@@ -82,6 +234,12 @@ fn init_cli() -> clap::ArgMatches {
         .arg(arg!(
             -d --debug ... "Turn debugging information on"
         ))
+        .arg(
+            arg!(--format <FORMAT> "print results as plain lines, a JSON array or TSV rows")
+                .global(true)
+                .value_parser(["plain", "json", "tsv"])
+                .default_value("plain"),
+        )
         .subcommand(
             Command::new("new")
                 .about("Create a new note")
@@ -90,13 +248,152 @@ fn init_cli() -> clap::ArgMatches {
         )
         .subcommand(
             Command::new("add")
-                .about("Add an existing file to notes")
-                .arg(arg!([name] "Full path to note to add")),
+                .about("Add existing files to notes")
+                .arg(arg!(<paths> ... "Paths to notes to add")),
+        )
+        .subcommand(
+            Command::new("templates")
+                .about("List existing templates")
+                .subcommand(
+                    Command::new("save")
+                        .about("Save an existing note as a new template")
+                        .arg(arg!(<note> "Note to copy into the config dir"))
+                        .arg(arg!(--id <ID> "id of the new template").required(true))
+                        .arg(arg!(-p --placeholders "Replace today's date, the note's name and its dir name with $DATE, $NAME and $PWD")),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List all known notes")
+                .arg(arg!(-a --archived "Include archived notes")),
         )
-        .subcommand(Command::new("templates").about("List existing templates"))
-        .subcommand(Command::new("list").about("List all known notes"))
         .subcommand(Command::new("clean").about("Remove nonexistent entries from `list`"))
-        // .subcommand(Command::new("search").about("Add an existing file to notes"))
+        .subcommand(
+            Command::new("mv")
+                .about("Move a note and rewrite links pointing to it")
+                .arg(arg!(<from> "Note to move"))
+                .arg(arg!(<to> "New path or directory for the note"))
+                .arg(arg!(-n --"dry-run" "Preview the rename and link changes as a diff")),
+        )
+        .subcommand(
+            Command::new("rm")
+                .about("Remove a note from `list`")
+                .arg(arg!(<note> "Note to remove"))
+                .arg(arg!(-D --delete "Also move the file into the trash dir")),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the git commits that changed a note")
+                .arg(arg!(<note> "Note in a git repository")),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Bring back a note's version from a git commit")
+                .arg(arg!(<note> "Note in a git repository"))
+                .arg(arg!(<rev> "Commit, as listed by `history`")),
+        )
+        .subcommand(
+            Command::new("archive")
+                .about("Move a note into the archive and hide it from `list`")
+                .arg(arg!(<note> "Note to archive")),
+        )
+        .subcommand(
+            Command::new("db")
+                .about("Manage the brain database")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("migrate")
+                        .about("Copy the brain into another storage backend")
+                        .arg(
+                            arg!(--to <BACKEND> "backend to migrate to")
+                                .required(true)
+                                .value_parser(["toml", "jsonl", "sqlite"]),
+                        )
+                        .arg(arg!(-o --out <PATH> "new data file, defaults to `data` with the backend's extension")),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the brain into other formats")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("html")
+                        .about("Render notes into a static site with indexes, backlinks and a search index")
+                        .arg(arg!(<outdir> "Directory to write the site to"))
+                        .arg(
+                            arg!(-t --tag <TAG> "only export notes with this frontmatter tag")
+                                .action(ArgAction::Append),
+                        ),
+                )
+                .subcommand(
+                    Command::new("json")
+                        .about("Dump every entry with its metadata as JSON")
+                        .arg(arg!(-o --out <FILE> "file to write, defaults to stdout"))
+                        .arg(arg!(-c --content "Include the contents of each note"))
+                        .arg(arg!(--ndjson "Write one entry per line into `--out` instead of an array")),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import notes from other tools")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("obsidian")
+                        .about("Add the notes of an Obsidian vault or markdown folder, and its templates")
+                        .arg(arg!(<vault> "Vault directory"))
+                        .arg(arg!(-c --convert "Rewrite embeds, aliased links and %%comments%% in place")),
+                )
+                .subcommand(
+                    Command::new("json")
+                        .about("Merge a dump from `export json` into the brain")
+                        .arg(arg!(<file> "JSON or NDJSON dump, `-` for stdin"))
+                        .arg(
+                            arg!(--conflict <RULE> "what to do with entries the brain already has")
+                                .value_parser(["keep", "replace", "merge"])
+                                .default_value("merge"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("tasks")
+                .about("List `- [ ]` tasks across all known notes")
+                .arg(
+                    arg!(-s --status <STATUS> "only show tasks with this status")
+                        .value_parser(["open", "done", "all"])
+                        .default_value("open"),
+                )
+                .arg(
+                    arg!(-t --tag <TAG> "only show tasks with this #tag").action(ArgAction::Append),
+                )
+                .arg(arg!(--"due-after" <DATE> "only show tasks due on or after YYYY-MM-DD"))
+                .arg(arg!(--"due-before" <DATE> "only show tasks due on or before YYYY-MM-DD"))
+                .arg(arg!(-a --archived "Include tasks in archived notes"))
+                .subcommand(
+                    Command::new("done")
+                        .about("Tick the task at `path:line`")
+                        .arg(arg!(<location> "Task location as printed by `tasks`")),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Print lines of known notes containing every word, ignoring case")
+                .arg(arg!(<query> ... "Words to search for"))
+                .arg(arg!(-a --archived "Include archived notes")),
+        )
+        .subcommand(Command::new("lsp").about("Run a language server for notes over stdio"))
+        .subcommand(
+            Command::new("watch")
+                .about("Keep the brain in sync with notes created, moved or deleted in the roots"),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Browse, search and create notes in a browser on localhost")
+                .arg(
+                    arg!(-p --port <PORT> "Port to listen on")
+                        .value_parser(clap::value_parser!(u16))
+                        .default_value("8080"),
+                ),
+        )
         .arg(arg!([name] "Note to operate on, or create if only arg given"))
         .arg(arg!(-t --template "select a template").action(ArgAction::Set))
         .get_matches()
@@ -122,21 +419,52 @@ fn parse_name_arg(
 }
 
 fn parse_template_arg(
+    data: &Data,
     cli_result: &clap::ArgMatches,
     template: &mut Option<String>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), MdbError> {
     if let Some(template_arg) = cli_result.get_one::<String>("template") {
         info!("Value for template: {}", template_arg);
-        if Data::template_file_exists(template_arg) {
+        if Data::template_file_exists(template_arg) || data.get_template(template_arg).is_some() {
             *template = Some(template_arg.to_owned());
             return Ok(());
         } else {
-            return Err(format!("No template named `{}`", template_arg).into());
+            return Err(MdbError::Template(format!(
+                "No template named `{}`",
+                template_arg
+            )));
         }
     }
     Ok(())
 }
 
+fn parse_task_filter(cli_result: &clap::ArgMatches) -> Result<TaskFilter, MdbError> {
+    let date = |arg: &str| -> Result<Option<NaiveDate>, MdbError> {
+        match cli_result.get_one::<String>(arg) {
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|err| MdbError::Input(format!("--{} {}: {}", arg, date, err))),
+            None => Ok(None),
+        }
+    };
+    let status = match cli_result.get_one::<String>("status").map(String::as_str) {
+        Some("done") => Status::Done,
+        Some("all") => Status::All,
+        _ => Status::Open,
+    };
+    Ok(TaskFilter {
+        status,
+        tags: cli_result
+            .get_many::<String>("tag")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        due_after: date("due-after")?,
+        due_before: date("due-before")?,
+        archived: cli_result.get_flag("archived"),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_derive::Serialize;
+
+/// How command results are printed, picked with `--format`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    /// Human readable lines, as mdb always printed them
+    #[default]
+    Plain,
+    /// A JSON array of records
+    Json,
+    /// A header row and one tab separated row per record
+    Tsv,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "plain" => Some(OutputFormat::Plain),
+            "json" => Some(OutputFormat::Json),
+            "tsv" => Some(OutputFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// One item of a command's result, with stable field names
+pub trait Record: serde::Serialize {
+    /// TSV header, in the order of `row`
+    const COLUMNS: &'static [&'static str];
+
+    fn row(&self) -> Vec<String>;
+
+    /// Line printed for the record in `Plain`, nothing when empty
+    fn plain(&self) -> String;
+}
+
+/// The result of a command, printed by `main` in the chosen format
+pub trait Output {
+    fn render(&self, format: OutputFormat) -> serde_json::Result<String>;
+}
+
+impl<T: Record> Output for Vec<T> {
+    fn render(&self, format: OutputFormat) -> serde_json::Result<String> {
+        Ok(match format {
+            OutputFormat::Plain => self
+                .iter()
+                .map(Record::plain)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            OutputFormat::Json => serde_json::to_string(self)?,
+            OutputFormat::Tsv => {
+                let mut rows = vec![T::COLUMNS.join("\t")];
+                rows.extend(self.iter().map(tsv_row));
+                rows.join("\n")
+            }
+        })
+    }
+}
+
+/// Line printed before any `line` of a command that prints records as they come, TSV's header
+pub fn header<T: Record>(format: OutputFormat) -> Option<String> {
+    (format == OutputFormat::Tsv).then(|| T::COLUMNS.join("\t"))
+}
+
+/// `record` on a line of its own, with JSON as one object per line
+pub fn line<T: Record>(record: &T, format: OutputFormat) -> serde_json::Result<String> {
+    Ok(match format {
+        OutputFormat::Plain => record.plain(),
+        OutputFormat::Json => serde_json::to_string(record)?,
+        OutputFormat::Tsv => tsv_row(record),
+    })
+}
+
+fn tsv_row<T: Record>(record: &T) -> String {
+    let row: Vec<String> = record.row().iter().map(|cell| escape(cell)).collect();
+    row.join("\t")
+}
+
+/// A file a command wrote, like a saved template or an export
+#[derive(Serialize, Debug)]
+pub struct Written {
+    pub path: PathBuf,
+}
+
+impl Record for Written {
+    const COLUMNS: &'static [&'static str] = &["path"];
+
+    fn row(&self) -> Vec<String> {
+        vec![path(&self.path)]
+    }
+
+    fn plain(&self) -> String {
+        path(&self.path)
+    }
+}
+
+/// Escapes tabs, newlines and backslashes so each record stays on one TSV row
+fn escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+pub fn path(path: &Path) -> String {
+    path.to_str().unwrap_or_default().to_string()
+}
+
+/// Empty for a missing value
+pub fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+/// RFC 3339 timestamp, empty when missing
+pub fn date(date: &Option<DateTime<Utc>>) -> String {
+    date.map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{header, line, Output, OutputFormat, Written};
+
+    #[test]
+    fn test_render() {
+        let written = vec![
+            Written {
+                path: PathBuf::from("/notes/a.md"),
+            },
+            Written {
+                path: PathBuf::from("/notes/tab\there.md"),
+            },
+        ];
+        assert_eq!(
+            written.render(OutputFormat::Plain).unwrap(),
+            "/notes/a.md\n/notes/tab\there.md"
+        );
+        assert_eq!(
+            written.render(OutputFormat::Json).unwrap(),
+            r#"[{"path":"/notes/a.md"},{"path":"/notes/tab\there.md"}]"#
+        );
+        assert_eq!(
+            written.render(OutputFormat::Tsv).unwrap(),
+            "path\n/notes/a.md\n/notes/tab\\there.md"
+        );
+        assert_eq!(
+            Vec::<Written>::new().render(OutputFormat::Json).unwrap(),
+            "[]"
+        );
+        assert_eq!(
+            line(&written[0], OutputFormat::Json).unwrap(),
+            r#"{"path":"/notes/a.md"}"#
+        );
+        assert_eq!(
+            header::<Written>(OutputFormat::Tsv).as_deref(),
+            Some("path")
+        );
+        assert_eq!(header::<Written>(OutputFormat::Plain), None);
+    }
+}
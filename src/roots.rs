@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use serde_derive::Deserialize;
+
+const ROOT_PREFIX: char = '$';
+
+/// Named directories entries are stored relative to, e.g. `notes = "~/notes"`,
+/// so the same brain resolves on machines with different home directories
+#[derive(Deserialize, Debug, Default)]
+pub struct Roots(BTreeMap<String, String>);
+
+impl Roots {
+    fn home() -> PathBuf {
+        dirs::home_dir().unwrap_or_default()
+    }
+
+    pub fn dirs(&self) -> Vec<(&str, PathBuf)> {
+        self.0
+            .iter()
+            .map(|(name, dir)| {
+                (
+                    name.as_str(),
+                    PathBuf::from(shellexpand::tilde(dir).to_string()),
+                )
+            })
+            .collect()
+    }
+
+    /// Root a stored path starts with, as `$name`
+    fn name(stored: &Path) -> Option<&str> {
+        match stored.components().next()? {
+            Component::Normal(first) => first.to_str()?.strip_prefix(ROOT_PREFIX),
+            _ => None,
+        }
+    }
+
+    /// Stored form of an absolute `path`: `$root/...` for the deepest root containing it,
+    /// `~/...` below the home directory, or unchanged
+    pub fn portable(&self, path: &Path) -> PathBuf {
+        self.portable_from(path, &Roots::home())
+    }
+
+    fn portable_from(&self, path: &Path, home: &Path) -> PathBuf {
+        let deepest = self
+            .dirs()
+            .into_iter()
+            .filter(|(_, dir)| path.starts_with(dir))
+            .max_by_key(|(_, dir)| dir.components().count());
+        if let Some((name, dir)) = deepest {
+            let rest = path.strip_prefix(&dir).unwrap_or(path);
+            return PathBuf::from(format!("{}{}", ROOT_PREFIX, name)).join(rest);
+        }
+        match path.strip_prefix(home) {
+            Ok(rest) if home.components().count() > 1 => PathBuf::from("~").join(rest),
+            _ => path.to_owned(),
+        }
+    }
+
+    /// Absolute path for a stored one, left as is when its root isn't configured
+    pub fn resolve(&self, stored: &Path) -> PathBuf {
+        self.resolve_from(stored, &Roots::home())
+    }
+
+    fn resolve_from(&self, stored: &Path, home: &Path) -> PathBuf {
+        if let Ok(rest) = stored.strip_prefix("~") {
+            return home.join(rest);
+        }
+        let Some(name) = Roots::name(stored) else {
+            return stored.to_owned();
+        };
+        match self.0.get(name) {
+            Some(dir) => {
+                let rest = stored.iter().skip(1).collect::<PathBuf>();
+                PathBuf::from(shellexpand::tilde(dir).to_string()).join(rest)
+            }
+            None => stored.to_owned(),
+        }
+    }
+
+    /// Whether `path` can be checked for existence here: it resolved,
+    /// and any root it lives under is present on this machine
+    pub fn mounted(&self, path: &Path) -> bool {
+        if !path.is_absolute() {
+            return false;
+        }
+        self.dirs()
+            .iter()
+            .filter(|(_, dir)| path.starts_with(dir))
+            .all(|(_, dir)| dir.exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    use super::Roots;
+
+    fn roots() -> Roots {
+        Roots(BTreeMap::from([
+            ("notes".to_string(), "/data/notes".to_string()),
+            ("work".to_string(), "/data/notes/work".to_string()),
+        ]))
+    }
+
+    #[test]
+    fn test_portable_prefers_deepest_root() {
+        let home = Path::new("/home/alice");
+        let roots = roots();
+        assert_eq!(
+            roots.portable_from(Path::new("/data/notes/work/a.md"), home),
+            PathBuf::from("$work/a.md")
+        );
+        assert_eq!(
+            roots.portable_from(Path::new("/data/notes/b.md"), home),
+            PathBuf::from("$notes/b.md")
+        );
+        assert_eq!(
+            roots.portable_from(Path::new("/home/alice/c.md"), home),
+            PathBuf::from("~/c.md")
+        );
+        assert_eq!(
+            roots.portable_from(Path::new("/tmp/d.md"), home),
+            PathBuf::from("/tmp/d.md")
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let home = Path::new("/Users/alice");
+        let roots = roots();
+        assert_eq!(
+            roots.resolve_from(Path::new("~/c.md"), home),
+            PathBuf::from("/Users/alice/c.md")
+        );
+        assert_eq!(
+            roots.resolve_from(Path::new("$work/a.md"), home),
+            PathBuf::from("/data/notes/work/a.md")
+        );
+        assert_eq!(
+            roots.resolve_from(Path::new("$gone/e.md"), home),
+            PathBuf::from("$gone/e.md")
+        );
+        assert!(!roots.mounted(Path::new("$gone/e.md")));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::BTreeSet, fs, path::PathBuf};
+
+use log::info;
+use serde_derive::Serialize;
+
+use crate::{
+    brain::Brain,
+    config::Data,
+    error::MdbError,
+    output::{self, Record},
+};
+
+/// A line of a known note matching a search
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+}
+
+impl Record for Hit {
+    const COLUMNS: &'static [&'static str] = &["path", "line", "text"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.path),
+            self.line.to_string(),
+            self.text.clone(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        format!("{}:{}: {}", self.path.display(), self.line, self.text)
+    }
+}
+
+/// Whether `line` contains every lowercase term, ignoring case
+fn matches(line: &str, terms: &[String]) -> bool {
+    let line = line.to_lowercase();
+    terms.iter().all(|term| line.contains(term.as_str()))
+}
+
+pub struct Search {}
+impl Search {
+    /// Lines of known notes containing every word of `query`, ignoring case
+    pub fn notes(data: &Data, query: &str, archived: bool) -> Result<Vec<Hit>, MdbError> {
+        info!("Search::notes {:?}", query);
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+        let brain = Brain::load(data)?;
+        let entries: BTreeSet<&PathBuf> = brain
+            .entries
+            .iter()
+            .filter(|path| archived || !brain.is_archived(path))
+            .collect();
+        let mut hits = vec![];
+        for entry in entries {
+            let Ok(contents) = fs::read_to_string(entry) else {
+                info!("Search::notes skipping unreadable {:?}", entry);
+                continue;
+            };
+            for (index, line) in contents.lines().enumerate() {
+                if matches(line, &terms) {
+                    hits.push(Hit {
+                        path: entry.clone(),
+                        line: index + 1,
+                        text: line.trim().to_string(),
+                    });
+                }
+            }
+        }
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn test_matches_every_term() {
+        let terms = vec!["rust".to_string(), "lsp".to_string()];
+        assert!(matches("- [ ] Try the Rust LSP", &terms));
+        assert!(!matches("- [ ] Try the Rust compiler", &terms));
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::info;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, CONTROLS};
+use tiny_http::{Header, Method, Request, Response, Server as Http};
+use ulid::Ulid;
+
+use crate::{
+    brain::Brain,
+    config::Data,
+    error::MdbError,
+    export::{escape, href, layout, list, Site, INDEX},
+    search::Search,
+};
+
+const SEARCH: &str = "search";
+const NEW: &str = "new";
+const HTML: &str = "text/html; charset=utf-8";
+const JSON: &str = "application/json";
+
+/// Added to the navigation of every served page
+const NAV: &str = concat!(
+    " · <form action=\"/search\" style=\"display: inline\">",
+    "<input name=\"q\" placeholder=\"Search\"></form>",
+    " · <a href=\"/new\">New note</a>"
+);
+
+type Reply = Response<Cursor<Vec<u8>>>;
+/// Modification time and archived state of every brain entry, to tell when the site changed
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>, bool)>;
+
+/// Splits a request URL into its decoded path, without the leading `/`, and query
+fn parse_url(url: &str) -> (String, Vec<(String, String)>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut path = percent_decode_str(path.trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .to_string();
+    if path.is_empty() || path.ends_with('/') {
+        path.push_str(INDEX);
+    }
+    let query = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    (path, query)
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> &'a str {
+    fields
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.trim())
+        .unwrap_or_default()
+}
+
+/// Value of the request header `name`, if it was sent
+fn request_header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Whether `host`, as in a `Host` header, is this server rather than a name rebound to it
+fn is_local(host: &str, port: u16) -> bool {
+    [format!("127.0.0.1:{}", port), format!("localhost:{}", port)].contains(&host.to_string())
+}
+
+/// Checks a note name from the form stays a file name in the template's dir
+fn check_name(name: &str) -> Result<(), MdbError> {
+    let path = Path::new(name);
+    let plain = path.components().count() == 1
+        && matches!(path.components().next(), Some(Component::Normal(_)));
+    if !plain || name.contains(['/', '\\']) {
+        return Err(MdbError::Input(format!(
+            "`{}` is not a note name, leave out directories",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Header values are ASCII")
+}
+
+fn page(contents: String, content_type: &str) -> Reply {
+    Response::from_string(contents).with_header(header("Content-Type", content_type))
+}
+
+fn redirect(url: &str) -> Reply {
+    let url = utf8_percent_encode(url, CONTROLS).to_string();
+    Response::from_string("")
+        .with_status_code(303)
+        .with_header(header("Location", &url))
+}
+
+/// URL of a site file, from the root
+fn url(path: &Path) -> String {
+    format!("/{}", href(Path::new(""), path))
+}
+
+/// The site as last rendered, and the brain it was rendered from
+struct Cache {
+    fingerprint: Fingerprint,
+    site: Site,
+    files: BTreeMap<PathBuf, String>,
+}
+
+pub struct Server<'a> {
+    data: &'a Data,
+    /// Where notes are created
+    pwd: &'a Path,
+    port: u16,
+    /// Sent with the new note form, so only pages of this server can create notes
+    token: String,
+    cache: Option<Cache>,
+}
+
+impl<'a> Server<'a> {
+    /// Serves the brain on localhost until the process is stopped, creating notes in `pwd`
+    pub(crate) fn run(data: &Data, pwd: &Path, port: u16) -> Result<(), MdbError> {
+        let address = format!("127.0.0.1:{}", port);
+        let http = Http::http(&address)
+            .map_err(|err| MdbError::Input(format!("Can't listen on {}: {}", address, err)))?;
+        println!("Serving the brain on http://{}", address);
+        let mut server = Server {
+            data,
+            pwd,
+            port,
+            token: Ulid::new().to_string(),
+            cache: None,
+        };
+        for mut request in http.incoming_requests() {
+            info!("Server::run {} {}", request.method(), request.url());
+            let reply = match server.handle(&mut request) {
+                Ok(reply) => reply,
+                Err(err) => {
+                    info!("Server::run {} failed: {}", request.url(), err);
+                    let status = match err {
+                        MdbError::Input(_) | MdbError::Template(_) => 400,
+                        _ => 500,
+                    };
+                    let body = format!("<h1>Error</h1>\n<p>{}</p>\n", escape(&err.to_string()));
+                    page(layout("Error", Path::new(""), NAV, &body), HTML).with_status_code(status)
+                }
+            };
+            if let Err(err) = request.respond(reply) {
+                info!("Server::run responding failed: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the request comes from a page of this server: its `Host` is this server,
+    /// and so is the `Origin` of a POST if the browser sent one
+    fn allowed(&self, request: &Request) -> bool {
+        let host = request_header(request, "Host").unwrap_or_default();
+        let origin = match request.method() {
+            Method::Get => None,
+            _ => request_header(request, "Origin"),
+        };
+        is_local(host, self.port)
+            && origin.is_none_or(|origin| {
+                origin
+                    .strip_prefix("http://")
+                    .is_some_and(|origin| is_local(origin, self.port))
+            })
+    }
+
+    fn handle(&mut self, request: &mut Request) -> Result<Reply, MdbError> {
+        if !self.allowed(request) {
+            info!("Server::handle refusing {:?}", request.headers());
+            return Ok(Response::from_string("").with_status_code(403));
+        }
+        let (path, query) = parse_url(request.url());
+        match (request.method(), path.as_str()) {
+            (Method::Get, SEARCH) => self.search(field(&query, "q")),
+            (Method::Get, NEW) => Ok(self.form()),
+            (Method::Post, NEW) => {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body)?;
+                let fields: Vec<(String, String)> = form_urlencoded::parse(body.as_bytes())
+                    .into_owned()
+                    .collect();
+                if field(&fields, "token") != self.token {
+                    return Ok(Response::from_string("").with_status_code(403));
+                }
+                self.create(field(&fields, "template"), field(&fields, "name"))
+            }
+            (Method::Get, path) => {
+                let files = &self.site()?.files;
+                Ok(match files.get(Path::new(path)) {
+                    Some(contents) if path.ends_with(".json") => page(contents.clone(), JSON),
+                    Some(contents) => page(contents.clone(), HTML),
+                    None => {
+                        let body = format!("<h1>Not found</h1>\n<p>{}</p>\n", escape(path));
+                        page(layout("Not found", Path::new(""), NAV, &body), HTML)
+                            .with_status_code(404)
+                    }
+                })
+            }
+            _ => Ok(Response::from_string("").with_status_code(405)),
+        }
+    }
+
+    /// The rendered site, rendered again only when a note or the brain changed since
+    fn site(&mut self) -> Result<&Cache, MdbError> {
+        let brain = Brain::load(self.data)?;
+        let mut fingerprint: Fingerprint = brain
+            .entries
+            .iter()
+            .map(|path| {
+                let modified = path.metadata().and_then(|meta| meta.modified()).ok();
+                (path.clone(), modified, brain.is_archived(path))
+            })
+            .collect();
+        fingerprint.sort();
+        match self.cache.take() {
+            Some(cache) if cache.fingerprint == fingerprint => self.cache = Some(cache),
+            _ => {
+                info!("Server::site rendering");
+                let site = Site::build(self.data, &[])?;
+                let files = site.files(NAV, false)?;
+                self.cache = Some(Cache {
+                    fingerprint,
+                    site,
+                    files,
+                });
+            }
+        }
+        Ok(self.cache.as_ref().expect("The cache was just filled"))
+    }
+
+    /// Results of the same search as `mdb search`, linked to the notes' pages
+    fn search(&mut self, query: &str) -> Result<Reply, MdbError> {
+        let hits = Search::notes(self.data, query, false)?;
+        let site = &self.site()?.site;
+        let hits: Vec<(String, String)> = hits
+            .into_iter()
+            .filter_map(|hit| {
+                let (out, title) = site.page(&hit.path)?;
+                Some((url(out), format!("{}:{}: {}", title, hit.line, hit.text)))
+            })
+            .collect();
+        let mut body = format!(
+            "<h1>Search</h1>\n<form action=\"/search\"><input name=\"q\" value=\"{}\"> <button>Search</button></form>\n",
+            escape(query)
+        );
+        body.push_str(&list(
+            hits.iter().map(|(url, text)| (url.clone(), text.as_str())),
+        ));
+        if hits.is_empty() && !query.is_empty() {
+            body.push_str("<p>No matches</p>\n");
+        }
+        Ok(page(layout("Search", Path::new(""), NAV, &body), HTML))
+    }
+
+    /// Form to create a note from one of the `[[templates]]`
+    fn form(&self) -> Reply {
+        let options: Vec<String> = self
+            .data
+            .templates
+            .iter()
+            .map(|template| format!("<option>{}</option>", escape(template.id())))
+            .collect();
+        let body = format!(
+            concat!(
+                "<h1>New note</h1>\n<form method=\"post\" action=\"/new\">\n",
+                "<input type=\"hidden\" name=\"token\" value=\"{}\">\n",
+                "<label>Template <select name=\"template\">{}</select></label>\n",
+                "<label>Name <input name=\"name\" placeholder=\"from the template\"></label>\n",
+                "<button>Create</button>\n</form>\n"
+            ),
+            self.token,
+            options.join("")
+        );
+        page(layout("New note", Path::new(""), NAV, &body), HTML)
+    }
+
+    /// Creates a note from a template without opening it, and redirects to its page
+    fn create(&mut self, id: &str, name: &str) -> Result<Reply, MdbError> {
+        let data = self.data;
+        let template = data
+            .get_template(&id.to_string())
+            .ok_or_else(|| MdbError::Template(format!("No template named `{}`", id)))?;
+        let name = match name {
+            "" => template.name(data, self.pwd)?,
+            name => {
+                check_name(name)?;
+                name.to_string()
+            }
+        };
+        let path: PathBuf = template.create(data, self.pwd.to_owned(), &name, false)?;
+        info!("Server::create {:?}", path);
+        Ok(match self.site()?.site.page(&path) {
+            Some((out, _)) => redirect(&url(out)),
+            None => redirect("/"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_name, field, is_local, parse_url};
+
+    #[test]
+    fn test_parse_url() {
+        let (path, _) = parse_url("/");
+        assert_eq!(path, "index.html");
+        let (path, _) = parse_url("/work/my%20note.html");
+        assert_eq!(path, "work/my note.html");
+        let (path, query) = parse_url("/search?q=rust+lsp%21");
+        assert_eq!(path, "search");
+        assert_eq!(field(&query, "q"), "rust lsp!");
+        assert_eq!(field(&query, "missing"), "");
+    }
+
+    #[test]
+    fn test_rejects_other_hosts_and_paths() {
+        assert!(is_local("127.0.0.1:8080", 8080));
+        assert!(is_local("localhost:8080", 8080));
+        assert!(!is_local("evil.example:8080", 8080));
+        assert!(!is_local("127.0.0.1:9000", 8080));
+
+        assert!(check_name("my note").is_ok());
+        assert!(check_name("/home/me/victim/pwned").is_err());
+        assert!(check_name("../victim/up").is_err());
+        assert!(check_name("..").is_err());
+        assert!(check_name("work/up").is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use rusqlite::{params, Connection};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    brain::{BrainData, Meta},
+    error::{MdbError, PathContext},
+};
+
+/// Previous versions of the data file kept by `write_atomic`
+const BACKUPS: usize = 3;
+
+/// Where `BrainData` is persisted
+pub trait BrainStore {
+    fn load(&self) -> Result<BrainData, MdbError>;
+
+    fn save(&self, brain: &BrainData) -> Result<(), MdbError>;
+
+    /// Adds `paths`, filling in whatever `meta` they don't have yet.
+    /// Stores that can do so without a full rewrite override this.
+    fn upsert(&self, paths: &[PathBuf], meta: Option<&Meta>) -> Result<(), MdbError> {
+        let mut brain = self.load()?;
+        for path in paths {
+            brain.upsert(path, meta);
+        }
+        self.save(&brain)
+    }
+
+    /// Increments and returns `counter`
+    fn next_counter(&self, counter: &str) -> Result<u64, MdbError> {
+        let mut brain = self.load()?;
+        let next = brain.counters.get(counter).copied().unwrap_or_default() + 1;
+        brain.counters.insert(counter.to_owned(), next);
+        self.save(&brain)?;
+        Ok(next)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Toml,
+    Jsonl,
+    Sqlite,
+}
+
+impl Backend {
+    pub fn from_path(path: &Path) -> Backend {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl" | "ndjson" | "json") => Backend::Jsonl,
+            Some("sqlite" | "sqlite3" | "db") => Backend::Sqlite,
+            _ => Backend::Toml,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "toml" => Some(Backend::Toml),
+            "jsonl" => Some(Backend::Jsonl),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Backend::Toml => "toml",
+            Backend::Jsonl => "jsonl",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    pub fn store(&self, file: PathBuf) -> Box<dyn BrainStore> {
+        match self {
+            Backend::Toml => Box::new(TomlStore { file }),
+            Backend::Jsonl => Box::new(JsonLinesStore { file }),
+            Backend::Sqlite => Box::new(SqliteStore { file }),
+        }
+    }
+}
+
+/// A data file that can't be parsed or written
+fn corrupt(file: &Path, err: impl Display) -> MdbError {
+    MdbError::Brain(format!("{}: {}", file.display(), err))
+}
+
+/// Creates the directory holding the data `file`
+fn create_parent(file: &Path) -> Result<&Path, MdbError> {
+    let dir = file
+        .parent()
+        .ok_or_else(|| MdbError::Config(format!("`data` {:?} must point to a file", file)))?;
+    fs::create_dir_all(dir).at(dir)?;
+    Ok(dir)
+}
+
+/// `<file>.<suffix>` next to `file`, creating its directory
+pub fn sibling(file: &Path, suffix: &str) -> Result<PathBuf, MdbError> {
+    let dir = create_parent(file)?;
+    let mut name = file.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(suffix);
+    Ok(dir.join(name))
+}
+
+/// Writes to a temporary file and renames it over `file`,
+/// keeping the previous `BACKUPS` versions as `<file>.bak.<n>`
+fn write_atomic(file: &Path, contents: &[u8]) -> Result<(), MdbError> {
+    let temp_file = sibling(file, &format!("tmp.{}", std::process::id()))?;
+    let mut temp = File::create(&temp_file).at(&temp_file)?;
+    temp.write_all(contents).at(&temp_file)?;
+    temp.sync_all().at(&temp_file)?;
+
+    if file.exists() {
+        for generation in (1..BACKUPS).rev() {
+            let older = sibling(file, &format!("bak.{}", generation))?;
+            if older.exists() {
+                let newer = sibling(file, &format!("bak.{}", generation + 1))?;
+                fs::rename(&older, newer).at(&older)?;
+            }
+        }
+        fs::copy(file, sibling(file, "bak.1")?).at(file)?;
+    }
+    fs::rename(&temp_file, file).at(file)?;
+    Ok(())
+}
+
+pub struct TomlStore {
+    file: PathBuf,
+}
+
+impl BrainStore for TomlStore {
+    fn load(&self) -> Result<BrainData, MdbError> {
+        info!("TomlStore::load {:?}", self.file);
+        if !self.file.exists() {
+            return Ok(BrainData::default());
+        }
+        let contents = fs::read_to_string(&self.file).at(&self.file)?;
+        toml::from_str(&contents).map_err(|err| corrupt(&self.file, err))
+    }
+
+    fn save(&self, brain: &BrainData) -> Result<(), MdbError> {
+        info!("TomlStore::save {:?}", self.file);
+        let contents = toml::to_string(brain).map_err(|err| corrupt(&self.file, err))?;
+        write_atomic(&self.file, contents.as_bytes())
+    }
+}
+
+/// One line per entry, later lines win over earlier ones for the same path
+#[derive(Serialize, Deserialize, Debug)]
+struct Record {
+    path: PathBuf,
+    #[serde(flatten)]
+    meta: Meta,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    removed: bool,
+}
+
+/// A line of a JSON Lines brain, either an entry or a counter
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum Line {
+    Counter { counter: String, value: u64 },
+    Entry(Record),
+}
+
+pub struct JsonLinesStore {
+    file: PathBuf,
+}
+
+impl JsonLinesStore {
+    fn record(brain: &BrainData, path: &Path) -> Record {
+        Record {
+            path: path.to_owned(),
+            meta: brain.meta.get(path).cloned().unwrap_or_default(),
+            removed: false,
+        }
+    }
+
+    /// Reads the brain, and whether the file ends in a complete line.
+    /// An unfinished last line, from an append that was cut short, is left out.
+    fn read(&self) -> Result<(BrainData, bool), MdbError> {
+        let mut brain = BrainData::default();
+        if !self.file.exists() {
+            return Ok((brain, true));
+        }
+        let contents = fs::read_to_string(&self.file).at(&self.file)?;
+        let complete = contents.is_empty() || contents.ends_with('\n');
+        let count = contents.lines().count();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line = match serde_json::from_str(line) {
+                Ok(line) => line,
+                Err(err) if !complete && number + 1 == count => {
+                    info!("JsonLinesStore::read ignoring unfinished line: {}", err);
+                    continue;
+                }
+                Err(err) => {
+                    return Err(corrupt(&self.file, format!("line {}: {}", number + 1, err)))
+                }
+            };
+            let record = match line {
+                Line::Counter { counter, value } => {
+                    brain.counters.insert(counter, value);
+                    continue;
+                }
+                Line::Entry(record) => record,
+            };
+            if record.removed {
+                brain.remove(&record.path);
+                continue;
+            }
+            brain.entries.insert(record.path.clone());
+            match record.meta.is_empty() {
+                true => brain.meta.remove(&record.path),
+                false => brain.meta.insert(record.path, record.meta),
+            };
+        }
+        Ok((brain, complete))
+    }
+}
+
+impl BrainStore for JsonLinesStore {
+    fn load(&self) -> Result<BrainData, MdbError> {
+        info!("JsonLinesStore::load {:?}", self.file);
+        Ok(self.read()?.0)
+    }
+
+    /// Rewrites the whole file, compacting superseded lines
+    fn save(&self, brain: &BrainData) -> Result<(), MdbError> {
+        info!("JsonLinesStore::save {:?}", self.file);
+        let mut contents = String::new();
+        for path in brain.entries.iter().collect::<BTreeSet<_>>() {
+            contents.push_str(
+                &serde_json::to_string(&JsonLinesStore::record(brain, path))
+                    .map_err(|err| corrupt(&self.file, err))?,
+            );
+            contents.push('\n');
+        }
+        for (counter, value) in &brain.counters {
+            let line = Line::Counter {
+                counter: counter.clone(),
+                value: *value,
+            };
+            contents
+                .push_str(&serde_json::to_string(&line).map_err(|err| corrupt(&self.file, err))?);
+            contents.push('\n');
+        }
+        write_atomic(&self.file, contents.as_bytes())
+    }
+
+    /// Appends a line per path, or rewrites the file if its last line is unfinished
+    fn upsert(&self, paths: &[PathBuf], meta: Option<&Meta>) -> Result<(), MdbError> {
+        let (mut brain, complete) = self.read()?;
+        if !complete {
+            for path in paths {
+                brain.upsert(path, meta);
+            }
+            return self.save(&brain);
+        }
+        let mut lines = String::new();
+        for path in paths {
+            brain.upsert(path, meta);
+            lines.push_str(
+                &serde_json::to_string(&JsonLinesStore::record(&brain, path))
+                    .map_err(|err| corrupt(&self.file, err))?,
+            );
+            lines.push('\n');
+        }
+        create_parent(&self.file)?;
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .at(&self.file)?;
+        file.write_all(lines.as_bytes()).at(&self.file)?;
+        file.sync_all().at(&self.file)?;
+        Ok(())
+    }
+}
+
+pub struct SqliteStore {
+    file: PathBuf,
+}
+
+impl SqliteStore {
+    fn open(&self) -> Result<Connection, MdbError> {
+        create_parent(&self.file)?;
+        let connection = Connection::open(&self.file)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS entries (
+                path TEXT PRIMARY KEY,
+                template TEXT,
+                created TEXT,
+                opened TEXT,
+                archived INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS counters (
+                name TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            )",
+        )?;
+        Ok(connection)
+    }
+}
+
+impl BrainStore for SqliteStore {
+    fn load(&self) -> Result<BrainData, MdbError> {
+        info!("SqliteStore::load {:?}", self.file);
+        let mut brain = BrainData::default();
+        if !self.file.exists() {
+            return Ok(brain);
+        }
+        let connection = self.open()?;
+        let mut select =
+            connection.prepare("SELECT path, template, created, opened, archived FROM entries")?;
+        let rows = select.query_map([], |row| {
+            Ok(Record {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                meta: Meta {
+                    template: row.get(1)?,
+                    created: row.get(2)?,
+                    opened: row.get(3)?,
+                    archived: row.get(4)?,
+                },
+                removed: false,
+            })
+        })?;
+        for row in rows {
+            let record = row?;
+            brain.entries.insert(record.path.clone());
+            if !record.meta.is_empty() {
+                brain.meta.insert(record.path, record.meta);
+            }
+        }
+        let mut select = connection.prepare("SELECT name, value FROM counters")?;
+        let rows = select.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (name, value) = row?;
+            brain.counters.insert(name, value);
+        }
+        Ok(brain)
+    }
+
+    fn save(&self, brain: &BrainData) -> Result<(), MdbError> {
+        info!("SqliteStore::save {:?}", self.file);
+        let mut connection = self.open()?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM entries", [])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO entries (path, template, created, opened, archived)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for path in &brain.entries {
+                let meta = brain.meta.get(path).cloned().unwrap_or_default();
+                insert.execute(params![
+                    path.to_string_lossy(),
+                    meta.template,
+                    meta.created,
+                    meta.opened,
+                    meta.archived
+                ])?;
+            }
+            transaction.execute("DELETE FROM counters", [])?;
+            let mut insert =
+                transaction.prepare("INSERT INTO counters (name, value) VALUES (?1, ?2)")?;
+            for (name, value) in &brain.counters {
+                insert.execute(params![name, value])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn upsert(&self, paths: &[PathBuf], meta: Option<&Meta>) -> Result<(), MdbError> {
+        info!("SqliteStore::upsert {:?}", paths);
+        let mut connection = self.open()?;
+        let meta = meta.cloned().unwrap_or_default();
+        let transaction = connection.transaction()?;
+        {
+            let mut upsert = transaction.prepare(
+                "INSERT INTO entries (path, template, created, opened) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (path) DO UPDATE SET
+                    template = COALESCE(template, excluded.template),
+                    created = COALESCE(created, excluded.created),
+                    opened = COALESCE(opened, excluded.opened)",
+            )?;
+            for path in paths {
+                upsert.execute(params![
+                    path.to_string_lossy(),
+                    meta.template,
+                    meta.created,
+                    meta.opened
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn next_counter(&self, counter: &str) -> Result<u64, MdbError> {
+        let connection = self.open()?;
+        Ok(connection.query_row(
+            "INSERT INTO counters (name, value) VALUES (?1, 1)
+             ON CONFLICT (name) DO UPDATE SET value = value + 1
+             RETURNING value",
+            params![counter],
+            |row| row.get(0),
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use chrono::Utc;
+
+    use super::Backend;
+    use crate::brain::Meta;
+
+    #[test]
+    fn test_backend_from_path() {
+        assert_eq!(Backend::from_path(Path::new("db.toml")), Backend::Toml);
+        assert_eq!(Backend::from_path(Path::new("db.ndjson")), Backend::Jsonl);
+        assert_eq!(Backend::from_path(Path::new("db.sqlite")), Backend::Sqlite);
+    }
+
+    #[test]
+    fn test_stores_round_trip() {
+        let dir = env::temp_dir().join(format!("mdb-store-test-{}", std::process::id()));
+        for backend in [Backend::Toml, Backend::Jsonl, Backend::Sqlite] {
+            let store = backend.store(dir.join(format!("db.{}", backend.extension())));
+            let meta = Meta {
+                template: Some("daily".into()),
+                created: Some(Utc::now()),
+                ..Default::default()
+            };
+            store.upsert(&["/n/a.md".into()], None).unwrap();
+            store.upsert(&["/n/b.md".into()], Some(&meta)).unwrap();
+            store.upsert(&["/n/a.md".into()], Some(&meta)).unwrap();
+            let brain = store.load().unwrap();
+            assert_eq!(brain.entries.len(), 2, "{:?}", backend);
+            assert_eq!(brain.meta[Path::new("/n/a.md")].template, meta.template);
+
+            let mut brain = brain;
+            brain.remove(Path::new("/n/b.md"));
+            store.save(&brain).unwrap();
+            let brain = store.load().unwrap();
+            assert_eq!(brain.entries.len(), 1, "{:?}", backend);
+            assert!(brain.meta[Path::new("/n/a.md")].created.is_some());
+
+            assert_eq!(store.next_counter("zk").unwrap(), 1);
+            assert_eq!(store.next_counter("zk").unwrap(), 2, "{:?}", backend);
+            assert_eq!(store.load().unwrap().entries.len(), 1, "{:?}", backend);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_jsonl_unfinished_line() {
+        let dir = env::temp_dir().join(format!("mdb-jsonl-test-{}", std::process::id()));
+        let file = dir.join("db.jsonl");
+        let store = Backend::Jsonl.store(file.clone());
+        store.upsert(&["/n/a.md".into()], None).unwrap();
+        let mut contents = fs::read_to_string(&file).unwrap();
+        contents.push_str("{\"path\":\"/n/b");
+        fs::write(&file, contents).unwrap();
+        assert_eq!(store.load().unwrap().entries.len(), 1);
+
+        store.upsert(&["/n/c.md".into()], None).unwrap();
+        assert_eq!(store.load().unwrap().entries.len(), 2);
+        assert!(fs::read_to_string(&file).unwrap().ends_with('\n'));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use log::info;
+use serde_derive::Serialize;
+
+use crate::{
+    brain::Brain,
+    config::Data,
+    error::{MdbError, PathContext},
+    format::{Format, Heading},
+    output::{self, Record},
+};
+
+const UNCHECKED: &str = "[ ]";
+const CHECKED: &str = "[x]";
+const MIGRATED: &str = "[>]";
+const ORG_TODO: &str = "TODO";
+const ORG_DONE: &str = "DONE";
+/// Replaces `TODO` in Org headlines carried over to a later note
+const ORG_MIGRATED: &str = "MOVED";
+
+/// Returns the marker offset and box of a GitHub-style `- [ ]` task, if the line is one.
+fn marker(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let rest = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+        .or_else(|| trimmed.strip_prefix("+ "))?;
+    Some((indent + 2, rest.get(..3)?))
+}
+
+fn unchecked_marker(line: &str) -> Option<usize> {
+    match marker(line) {
+        Some((at, UNCHECKED)) => Some(at),
+        _ => None,
+    }
+}
+
+/// Line numbers of the open tasks in `contents`, outside code blocks
+fn open_lines(contents: &str, format: Format) -> BTreeSet<usize> {
+    parse(contents, format)
+        .into_iter()
+        .filter(|task| !task.done)
+        .map(|task| task.line)
+        .collect()
+}
+
+/// The lines of open tasks, to roll over into a new note
+pub fn carryover(contents: &str, format: Format) -> String {
+    let open = open_lines(contents, format);
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(index, _)| open.contains(&(index + 1)))
+        .map(|(_, line)| line)
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn replace_marker(line: &str, at: usize, with: &str) -> String {
+    format!("{}{}{}", &line[..at], with, &line[at + UNCHECKED.len()..])
+}
+
+/// Marks the open tasks `carryover` took as `[>]`, or Org headlines as `MOVED`
+pub fn migrate(contents: &str, format: Format) -> String {
+    let open = open_lines(contents, format);
+    // Lines keep their endings, so CRLF notes stay CRLF
+    contents
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            if !open.contains(&(index + 1)) {
+                return line.to_string();
+            }
+            match (unchecked_marker(line), Task::org_todo(line)) {
+                (Some(at), _) => replace_marker(line, at, MIGRATED),
+                (None, Some(at)) => {
+                    format!(
+                        "{}{}{}",
+                        &line[..at],
+                        ORG_MIGRATED,
+                        &line[at + ORG_TODO.len()..]
+                    )
+                }
+                (None, None) => line.to_string(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Task {
+    pub line: usize,
+    pub done: bool,
+    pub text: String,
+    pub due: Option<NaiveDate>,
+    pub tags: Vec<String>,
+    pub priority: Option<String>,
+}
+
+impl Task {
+    /// Parses a task line with inline `@due(YYYY-MM-DD)`, `#tag` and `!priority` metadata
+    fn parse(line: &str, number: usize) -> Option<Task> {
+        let (at, state) = marker(line)?;
+        let done = match state {
+            UNCHECKED => false,
+            "[x]" | "[X]" => true,
+            _ => return None,
+        };
+        let text = line[at + UNCHECKED.len()..].trim().to_string();
+        let mut task = Task {
+            line: number,
+            done,
+            text,
+            due: None,
+            tags: vec![],
+            priority: None,
+        };
+        for word in task.text.split_whitespace() {
+            if let Some(due) = word
+                .strip_prefix("@due(")
+                .and_then(|due| due.strip_suffix(')'))
+            {
+                task.due = NaiveDate::parse_from_str(due, "%Y-%m-%d").ok();
+            } else if let Some(tag) = word.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+                task.tags.push(tag.to_string());
+            } else if let Some(priority) = word.strip_prefix('!').filter(|prio| !prio.is_empty()) {
+                task.priority = Some(priority.to_string());
+            }
+        }
+        Some(task)
+    }
+
+    /// Offset of the `TODO` keyword in an Org headline
+    fn org_todo(line: &str) -> Option<usize> {
+        let stars = line.chars().take_while(|c| *c == '*').count();
+        let keyword = line[stars..].strip_prefix(' ')?;
+        (stars > 0 && keyword.starts_with(&format!("{} ", ORG_TODO))).then_some(stars + 1)
+    }
+
+    /// Org `* TODO text` and `* DONE text` headlines
+    fn org(heading: &Heading) -> Option<Task> {
+        let (done, text) = match heading.text.split_once(' ') {
+            Some((ORG_TODO, text)) => (false, text),
+            Some((ORG_DONE, text)) => (true, text),
+            _ => return None,
+        };
+        let mut task = Task::parse(&format!("- {} {}", UNCHECKED, text), heading.line)?;
+        task.done = done;
+        Some(task)
+    }
+}
+
+pub fn parse(contents: &str, format: Format) -> Vec<Task> {
+    let mut fenced = false;
+    let mut tasks: Vec<Task> = contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            if format.is_fence(line) {
+                fenced = !fenced;
+            }
+            match fenced {
+                true => None,
+                false => Task::parse(line, index + 1),
+            }
+        })
+        .collect();
+    if format == Format::Org {
+        tasks.extend(format.headings(contents).iter().filter_map(Task::org));
+        tasks.sort_by_key(|task| task.line);
+    }
+    tasks
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Status {
+    #[default]
+    Open,
+    Done,
+    All,
+}
+
+#[derive(Debug, Default)]
+pub struct TaskFilter {
+    pub status: Status,
+    pub tags: Vec<String>,
+    pub due_after: Option<NaiveDate>,
+    pub due_before: Option<NaiveDate>,
+    pub archived: bool,
+}
+
+impl TaskFilter {
+    fn matches(&self, task: &Task) -> bool {
+        let status = match self.status {
+            Status::Open => !task.done,
+            Status::Done => task.done,
+            Status::All => true,
+        };
+        let tags = self.tags.iter().all(|tag| task.tags.contains(tag));
+        let due_range = self.due_after.is_some() || self.due_before.is_some();
+        let due = match task.due {
+            Some(due) => {
+                self.due_after.is_none_or(|after| due >= after)
+                    && self.due_before.is_none_or(|before| due <= before)
+            }
+            None => !due_range,
+        };
+        status && tags && due
+    }
+}
+
+/// A task and the note it is in, as listed by `tasks`
+#[derive(Serialize, Debug)]
+pub struct FoundTask {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+impl Record for FoundTask {
+    const COLUMNS: &'static [&'static str] =
+        &["path", "line", "done", "text", "due", "tags", "priority"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            output::path(&self.path),
+            self.task.line.to_string(),
+            self.task.done.to_string(),
+            self.task.text.clone(),
+            output::optional(&self.task.due),
+            self.task.tags.join(","),
+            output::optional(&self.task.priority),
+        ]
+    }
+
+    /// `path:line: [ ] text`, the location `tasks done` takes
+    fn plain(&self) -> String {
+        format!(
+            "{}:{}: {} {}",
+            self.path.display(),
+            self.task.line,
+            if self.task.done { CHECKED } else { UNCHECKED },
+            self.task.text
+        )
+    }
+}
+
+pub struct Tasks {}
+impl Tasks {
+    pub fn list(data: &Data, filter: &TaskFilter) -> Result<Vec<FoundTask>, MdbError> {
+        info!("Tasks::list {:?}", filter);
+        let brain = Brain::load(data)?;
+        let entries: BTreeSet<&PathBuf> = brain
+            .entries
+            .iter()
+            .filter(|path| filter.archived || !brain.is_archived(path))
+            .collect();
+        let mut found = vec![];
+        for entry in entries {
+            let Ok(contents) = fs::read_to_string(entry) else {
+                info!("Tasks::list skipping unreadable {:?}", entry);
+                continue;
+            };
+            let format = Format::from_path(entry);
+            for task in parse(&contents, format)
+                .into_iter()
+                .filter(|task| filter.matches(task))
+            {
+                found.push(FoundTask {
+                    path: entry.clone(),
+                    task,
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    /// Ticks the task at `location`, given as `path:line`
+    pub fn done(location: &str) -> Result<FoundTask, MdbError> {
+        info!("Tasks::done {}", location);
+        let invalid = MdbError::Input;
+        let (path, line) = location
+            .rsplit_once(':')
+            .ok_or_else(|| invalid(format!("Expected `path:line`, got `{}`", location)))?;
+        let line: usize = line
+            .parse()
+            .map_err(|_| invalid(format!("Invalid line number in `{}`", location)))?;
+        let contents = fs::read_to_string(path).at(Path::new(path))?;
+        let mut lines: Vec<String> = contents.split_inclusive('\n').map(String::from).collect();
+        let task = line
+            .checked_sub(1)
+            .and_then(|index| lines.get_mut(index))
+            .ok_or_else(|| invalid(format!("{} has no line {}", path, line)))?;
+        let format = Format::from_path(Path::new(path));
+        // Only what `tasks` lists, not task-like lines in code blocks
+        if !open_lines(&contents, format).contains(&line) {
+            return Err(invalid(format!("No open task at {}", location)));
+        }
+        let org_todo = match format {
+            Format::Org => Task::org_todo(task),
+            _ => None,
+        };
+        match (unchecked_marker(task), org_todo) {
+            (Some(at), _) => *task = replace_marker(task, at, CHECKED),
+            (None, Some(at)) => task.replace_range(at..at + ORG_TODO.len(), ORG_DONE),
+            (None, None) => return Err(invalid(format!("No open task at {}", location))),
+        }
+        let updated = lines.concat();
+        fs::write(path, &updated).at(Path::new(path))?;
+        let task = parse(&updated, format)
+            .into_iter()
+            .find(|task| task.line == line)
+            .ok_or_else(|| invalid(format!("No task at {}", location)))?;
+        Ok(FoundTask {
+            path: path.into(),
+            task,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use std::{env, fs};
+
+    use super::{carryover, migrate, parse, Status, TaskFilter, Tasks};
+    use crate::format::Format;
+
+    const NOTE: &str = "# 2026-10-17\n\n- [ ] open\n- [x] done\n  * [ ] nested\n- [>] moved\n";
+
+    #[test]
+    fn test_done_only_open_tasks() {
+        let note = env::temp_dir().join(format!("mdb-done-test-{}.md", std::process::id()));
+        fs::write(&note, "- [ ] open\r\n```\r\n- [ ] code sample\r\n```\r\n").unwrap();
+        let at = |line: usize| format!("{}:{}", note.display(), line);
+        assert!(Tasks::done(&at(3)).is_err());
+        assert!(Tasks::done(&at(2)).is_err());
+        assert_eq!(Tasks::done(&at(1)).unwrap().task.text, "open");
+        assert!(Tasks::done(&at(1)).is_err());
+        assert_eq!(
+            fs::read_to_string(&note).unwrap(),
+            "- [x] open\r\n```\r\n- [ ] code sample\r\n```\r\n"
+        );
+        fs::remove_file(&note).unwrap();
+    }
+
+    #[test]
+    fn test_carryover_only_unchecked() {
+        assert_eq!(
+            carryover(NOTE, Format::Markdown),
+            "- [ ] open\n  * [ ] nested"
+        );
+        let fenced = "- [ ] open\n```\n- [ ] code sample\n```\n";
+        assert_eq!(carryover(fenced, Format::Markdown), "- [ ] open");
+        assert_eq!(
+            migrate(fenced, Format::Markdown),
+            "- [>] open\n```\n- [ ] code sample\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_marks_unchecked() {
+        assert_eq!(
+            migrate(NOTE, Format::Markdown),
+            "# 2026-10-17\n\n- [>] open\n- [x] done\n  * [>] nested\n- [>] moved\n"
+        );
+        assert_eq!(
+            migrate("- [ ] open\r\n- [x] done\r\n", Format::Markdown),
+            "- [>] open\r\n- [x] done\r\n"
+        );
+    }
+
+    #[test]
+    fn test_carryover_org() {
+        let org =
+            "* Today\n** TODO call bob\n** DONE lunch\n#+begin_src\n- [ ] code sample\n#+end_src\n";
+        assert_eq!(carryover(org, Format::Org), "** TODO call bob");
+        assert_eq!(
+            migrate(org, Format::Org),
+            "* Today\n** MOVED call bob\n** DONE lunch\n#+begin_src\n- [ ] code sample\n#+end_src\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let tasks = parse(
+            "text\n- [ ] ship it @due(2026-10-20) #work !high\n- [X] old #home\n",
+            Format::Markdown,
+        );
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].line, 2);
+        assert!(!tasks[0].done);
+        assert_eq!(tasks[0].due, NaiveDate::from_ymd_opt(2026, 10, 20));
+        assert_eq!(tasks[0].tags, vec!["work"]);
+        assert_eq!(tasks[0].priority, Some("high".into()));
+        assert!(tasks[1].done);
+    }
+
+    #[test]
+    fn test_filter() {
+        let tasks = parse(
+            "- [ ] a @due(2026-10-20) #work\n- [ ] b #home\n- [x] c #work\n",
+            Format::Markdown,
+        );
+        let filter = TaskFilter {
+            tags: vec!["work".into()],
+            ..Default::default()
+        };
+        let open: Vec<_> = tasks.iter().filter(|task| filter.matches(task)).collect();
+        assert_eq!(open.len(), 1);
+        let filter = TaskFilter {
+            status: Status::All,
+            due_before: NaiveDate::from_ymd_opt(2026, 10, 19),
+            ..Default::default()
+        };
+        assert_eq!(tasks.iter().filter(|task| filter.matches(task)).count(), 0);
+    }
+
+    #[test]
+    fn test_parse_org_and_asciidoc() {
+        let org = parse(
+            "* TODO call #work\n** DONE write\n- [ ] box\n#+BEGIN_SRC\n- [ ] code\n#+END_SRC\n",
+            Format::Org,
+        );
+        let found: Vec<_> = org.iter().map(|task| (task.line, task.done)).collect();
+        assert_eq!(found, vec![(1, false), (2, true), (3, false)]);
+        assert_eq!(org[0].tags, vec!["work"]);
+
+        let adoc = parse(
+            "* [ ] one\n* [x] two\n----\n* [ ] code\n----\n",
+            Format::AsciiDoc,
+        );
+        assert_eq!(adoc.len(), 2);
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use log::info;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde_derive::Serialize;
+
+use crate::{
+    brain::{Brain, BrainData},
+    config::Data,
+    error::MdbError,
+    format::EXTENSIONS,
+    output::{self, OutputFormat, Record},
+};
+
+/// How long to wait for more events before applying a burst of them
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// A change to files below a root, as seen by the watcher
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Renamed(PathBuf, PathBuf),
+}
+
+/// An entry `watch` changed in the brain
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Synced {
+    /// `added`, `removed` or `moved`
+    pub change: &'static str,
+    pub path: PathBuf,
+    /// Where a moved note was before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+}
+
+impl Synced {
+    fn new(change: &'static str, path: &Path) -> Synced {
+        Synced {
+            change,
+            path: path.to_owned(),
+            from: None,
+        }
+    }
+}
+
+impl Record for Synced {
+    const COLUMNS: &'static [&'static str] = &["change", "path", "from"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.change.to_string(),
+            output::path(&self.path),
+            self.from.as_deref().map(output::path).unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        match &self.from {
+            Some(from) => format!("Moved {} to {}", from.display(), self.path.display()),
+            None if self.change == "added" => format!("Added {}", self.path.display()),
+            None => format!("Removed {}", self.path.display()),
+        }
+    }
+}
+
+/// Whether a file below one of `roots` is a note: a known extension, and no hidden dir or file
+fn is_note(roots: &[PathBuf], path: &Path) -> bool {
+    let Some(rest) = roots.iter().find_map(|root| path.strip_prefix(root).ok()) else {
+        return false;
+    };
+    let hidden = rest
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    !hidden && EXTENSIONS.iter().any(|(ext, _)| Some(*ext) == extension)
+}
+
+/// Turns a burst of events below `roots` into changes, pairing the halves of renames
+fn changes(roots: &[PathBuf], events: Vec<notify::Event>) -> Vec<Change> {
+    let mut changes = vec![];
+    let mut renamed_from = vec![];
+    let mut renamed_to = vec![];
+    for event in events {
+        let mut paths = event.paths.into_iter();
+        match event.kind {
+            EventKind::Create(_) => changes.extend(paths.map(Change::Created)),
+            EventKind::Remove(_) => changes.extend(paths.map(Change::Removed)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    renamed_from.push(from.clone());
+                    renamed_to.push(to.clone());
+                    changes.push(Change::Renamed(from, to));
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                changes.extend(paths.map(Change::Removed))
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                changes.extend(paths.map(Change::Created))
+            }
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                changes.extend(paths.map(|path| match path.exists() {
+                    true => Change::Created(path),
+                    false => Change::Removed(path),
+                }))
+            }
+            _ => {}
+        }
+    }
+    // A rename also shows up as its two halves, which would lose the note's metadata
+    changes.retain(|change| match change {
+        Change::Removed(path) => !renamed_from.contains(path),
+        Change::Created(path) => !renamed_to.contains(path),
+        Change::Renamed(..) => true,
+    });
+    // Like an editor's backup copy, a note renamed to something that isn't one is gone
+    let changes: Vec<Change> = changes
+        .into_iter()
+        .map(|change| match change {
+            Change::Renamed(from, to) if !to.is_dir() && !is_note(roots, &to) => {
+                Change::Removed(from)
+            }
+            change => change,
+        })
+        .collect();
+
+    // A note removed and written again, as editors save through a backup, is only modified
+    let mut keep = vec![true; changes.len()];
+    for (removed, change) in changes.iter().enumerate() {
+        let Change::Removed(path) = change else {
+            continue;
+        };
+        let created = changes
+            .iter()
+            .enumerate()
+            .skip(removed + 1)
+            .position(|(index, later)| keep[index] && *later == Change::Created(path.clone()));
+        if let Some(created) = created {
+            keep[removed] = false;
+            keep[removed + 1 + created] = false;
+        }
+    }
+    changes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(change, keep)| keep.then_some(change))
+        .collect()
+}
+
+/// Files below `path`, or `path` itself if it's a file
+fn files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        files.append(&mut self::files(&entry?.path())?);
+    }
+    Ok(files)
+}
+
+/// Applies `change` below `roots` to the brain, returning each entry it changed
+fn apply(brain: &mut BrainData, roots: &[PathBuf], change: Change) -> Vec<Synced> {
+    let mut applied = vec![];
+    match change {
+        Change::Created(path) => {
+            for file in files(&path).unwrap_or_default() {
+                if is_note(roots, &file) && file.is_file() && !brain.entries.contains(&file) {
+                    applied.push(Synced::new("added", &file));
+                    brain.upsert(&file, None);
+                }
+            }
+        }
+        Change::Removed(path) => {
+            let mut removed: Vec<PathBuf> = brain
+                .entries
+                .iter()
+                .filter(|entry| entry.starts_with(&path))
+                .cloned()
+                .collect();
+            removed.sort();
+            for entry in removed {
+                applied.push(Synced::new("removed", &entry));
+                brain.remove(&entry);
+            }
+        }
+        Change::Renamed(from, to) => {
+            let mut moved: Vec<PathBuf> = brain
+                .entries
+                .iter()
+                .filter(|entry| entry.starts_with(&from))
+                .cloned()
+                .collect();
+            if moved.is_empty() {
+                return apply(brain, roots, Change::Created(to));
+            }
+            moved.sort();
+            for entry in moved {
+                let rest = entry.strip_prefix(&from).unwrap_or(Path::new(""));
+                let target = match rest.as_os_str().is_empty() {
+                    true => to.clone(),
+                    false => to.join(rest),
+                };
+                brain.rename(&entry, &target);
+                applied.push(Synced {
+                    change: "moved",
+                    path: target,
+                    from: Some(entry),
+                });
+            }
+        }
+    }
+    applied
+}
+
+pub struct Watch {}
+impl Watch {
+    /// Keeps the brain in sync with notes created, moved or deleted below the configured roots,
+    /// printing each entry it changed in `format`
+    pub(crate) fn run(data: &Data, format: OutputFormat) -> Result<(), MdbError> {
+        let mut roots: Vec<PathBuf> = vec![];
+        for (name, dir) in data.config.roots.dirs() {
+            match dir.canonicalize() {
+                Ok(dir) => roots.push(dir),
+                Err(err) => eprintln!("Not watching ${} ({}): {}", name, dir.display(), err),
+            }
+        }
+        // Watching a root also covers the roots inside it
+        let nested: Vec<bool> = roots
+            .iter()
+            .map(|root| {
+                roots
+                    .iter()
+                    .any(|other| other != root && root.starts_with(other))
+            })
+            .collect();
+        let roots: Vec<PathBuf> = roots
+            .into_iter()
+            .zip(nested)
+            .filter_map(|(root, nested)| (!nested).then_some(root))
+            .collect();
+        if roots.is_empty() {
+            return Err(MdbError::Config(
+                "No roots to watch, name the dirs your notes live in under `[config.roots]`".into(),
+            ));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = RecommendedWatcher::new(sender, notify::Config::default())
+            .map_err(|err| MdbError::Io(io::Error::other(err)))?;
+        for root in &roots {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|err| MdbError::Io(io::Error::other(err)))?;
+            eprintln!("Watching {}", root.display());
+        }
+        if let Some(header) = output::header::<Synced>(format) {
+            println!("{}", header);
+        }
+
+        while let Ok(event) = receiver.recv() {
+            let mut events = vec![event];
+            loop {
+                match receiver.recv_timeout(SETTLE) {
+                    Ok(event) => events.push(event),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            let events: Vec<notify::Event> = events
+                .into_iter()
+                .filter_map(|event| match event {
+                    Ok(event) => Some(event),
+                    Err(err) => {
+                        eprintln!("Watch error: {}", err);
+                        None
+                    }
+                })
+                .collect();
+            let changes = changes(&roots, events);
+            if changes.is_empty() {
+                continue;
+            }
+            info!("Watch::run {:?}", changes);
+            let applied = Brain::update(data, |brain| {
+                changes
+                    .into_iter()
+                    .flat_map(|change| apply(brain, &roots, change))
+                    .collect::<Vec<Synced>>()
+            })?;
+            for synced in applied {
+                println!("{}", output::line(&synced, format)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use notify::{
+        event::{CreateKind, ModifyKind, RemoveKind, RenameMode},
+        Event, EventKind,
+    };
+
+    use crate::brain::{BrainData, Meta};
+
+    use super::{apply, changes, is_note, Change, Synced};
+    use crate::output::Record;
+
+    #[test]
+    fn test_changes_pair_renames() {
+        let rename = |mode| EventKind::Modify(ModifyKind::Name(mode));
+        let events = vec![
+            Event::new(rename(RenameMode::From)).add_path("/n/a.md".into()),
+            Event::new(rename(RenameMode::To)).add_path("/n/b.md".into()),
+            Event::new(rename(RenameMode::Both))
+                .add_path("/n/a.md".into())
+                .add_path("/n/b.md".into()),
+            Event::new(EventKind::Create(CreateKind::File)).add_path("/n/c.md".into()),
+        ];
+        let roots = [PathBuf::from("/n")];
+        assert_eq!(
+            changes(&roots, events),
+            vec![
+                Change::Renamed("/n/a.md".into(), "/n/b.md".into()),
+                Change::Created("/n/c.md".into()),
+            ]
+        );
+
+        // Saving through a backup: mv a.md a.md~, write a.md, rm a.md~
+        let events = vec![
+            Event::new(rename(RenameMode::Both))
+                .add_path("/n/a.md".into())
+                .add_path("/n/a.md~".into()),
+            Event::new(EventKind::Create(CreateKind::File)).add_path("/n/a.md".into()),
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path("/n/a.md~".into()),
+        ];
+        assert_eq!(
+            changes(&roots, events),
+            vec![Change::Removed("/n/a.md~".into())]
+        );
+    }
+
+    #[test]
+    fn test_apply_keeps_metadata_on_rename() {
+        let mut brain = BrainData::default();
+        let meta = Meta {
+            template: Some("daily".to_string()),
+            ..Meta::default()
+        };
+        brain.upsert(Path::new("/n/old/a.md"), Some(&meta));
+        brain.upsert(Path::new("/n/gone.md"), None);
+
+        let roots = [PathBuf::from("/n")];
+        let synced = apply(
+            &mut brain,
+            &roots,
+            Change::Renamed("/n/old".into(), "/n/new".into()),
+        );
+        assert_eq!(synced[0].plain(), "Moved /n/old/a.md to /n/new/a.md");
+        assert_eq!(synced[0].row(), vec!["moved", "/n/new/a.md", "/n/old/a.md"]);
+        let synced = apply(&mut brain, &roots, Change::Removed("/n/gone.md".into()));
+        assert_eq!(
+            synced,
+            vec![Synced::new("removed", Path::new("/n/gone.md"))]
+        );
+        let moved = PathBuf::from("/n/new/a.md");
+        assert_eq!(brain.entries.len(), 1);
+        assert!(brain.entries.contains(&moved));
+        assert_eq!(brain.meta[&moved].template.as_deref(), Some("daily"));
+
+        let roots = [PathBuf::from("/home/me/.notes")];
+        assert!(is_note(&roots, Path::new("/home/me/.notes/new/b.org")));
+        assert!(!is_note(
+            &roots,
+            Path::new("/home/me/.notes/.obsidian/b.md")
+        ));
+        assert!(!is_note(&roots, Path::new("/home/me/.notes/image.png")));
+        assert!(!is_note(&roots, Path::new("/tmp/c.md")));
+    }
+}